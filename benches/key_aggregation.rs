@@ -0,0 +1,24 @@
+#[macro_use]
+extern crate criterion;
+extern crate curv;
+extern crate multi_party_schnorr;
+
+use criterion::Criterion;
+use curv::GE;
+use multi_party_schnorr::protocols::aggsig::{KeyAgg, KeyPair};
+
+// run with and without --features parallel to compare the two paths, and with and without
+// --features musig_spec_v1 to compare hashing the key list per coefficient with hashing it once
+fn bench_key_aggregation(c: &mut Criterion) {
+    c.bench_function_over_inputs(
+        "key_aggregation_n",
+        |b, &&n| {
+            let pks: Vec<GE> = (0..n).map(|_| KeyPair::create().public_key).collect();
+            b.iter(|| KeyAgg::key_aggregation_n(&pks, 0).unwrap())
+        },
+        &[2, 16, 128, 256],
+    );
+}
+
+criterion_group!(benches, bench_key_aggregation);
+criterion_main!(benches);
@@ -0,0 +1,51 @@
+#[macro_use]
+extern crate criterion;
+extern crate multi_party_schnorr;
+
+use criterion::Criterion;
+use multi_party_schnorr::protocols::aggsig::session::MuSigSession;
+use multi_party_schnorr::protocols::aggsig::{ChallengeMode, EphemeralKey, KeyAgg, KeyPair};
+
+// one partial signature, the challenge computed outside the measured loop
+fn bench_sign(c: &mut Criterion) {
+    let message = b"message".to_vec();
+    let party1_key = KeyPair::create();
+    let party2_key = KeyPair::create();
+    let key_agg = KeyAgg::key_aggregation(&party1_key.public_key, &party2_key.public_key);
+    let ephemeral_key = EphemeralKey::create();
+    let r = ephemeral_key.keypair.public_key;
+    let challenge = EphemeralKey::hash_0(&r, &key_agg.apk, &message, ChallengeMode::Musig);
+    c.bench_function("EphemeralKey::sign", move |b| {
+        b.iter(|| EphemeralKey::sign(&ephemeral_key, &challenge, &party1_key, &key_agg.hash))
+    });
+}
+
+// a full {2,2} session with fresh keys: key aggregation, commitments, reveals, partial
+// signatures and the final verification in finalize
+fn bench_two_party_session(c: &mut Criterion) {
+    c.bench_function("2-of-2 MuSigSession", |b| {
+        b.iter(|| {
+            let message = b"message";
+            let party1_key = KeyPair::create();
+            let party2_key = KeyPair::create();
+            let party1_pk = party1_key.public_key;
+            let party2_pk = party2_key.public_key;
+            let party1_session = MuSigSession::new(party1_key, &party2_pk, message);
+            let party2_session = MuSigSession::new(party2_key, &party1_pk, message);
+
+            let party1_commitment = party1_session.first_message();
+            let party2_commitment = party2_session.first_message();
+            let party1_session = party1_session.receive_commitment(&party2_commitment);
+            let party2_session = party2_session.receive_commitment(&party1_commitment);
+            let party1_reveal = party1_session.second_message();
+            let party2_reveal = party2_session.second_message();
+            let party1_session = party1_session.receive_reveal(&party2_reveal).unwrap();
+            let party2_session = party2_session.receive_reveal(&party1_reveal).unwrap();
+            let party2_s = party2_session.partial_signature();
+            party1_session.finalize(&party2_s).unwrap()
+        })
+    });
+}
+
+criterion_group!(benches, bench_sign, bench_two_party_session);
+criterion_main!(benches);
@@ -0,0 +1,122 @@
+#[macro_use]
+extern crate criterion;
+extern crate curv;
+extern crate multi_party_schnorr;
+
+use criterion::Criterion;
+use curv::{BigInt, GE};
+use multi_party_schnorr::protocols::aggsig::{
+    batch_verify, CachedVerifier, EphemeralKey, KeyPair, Signature, Verifier,
+};
+
+const SIGNATURES: usize = 10_000;
+const BATCH_SIGNATURES: usize = 1_000;
+
+fn signatures() -> Vec<(Signature, GE, Vec<u8>)> {
+    (0..SIGNATURES)
+        .map(|i| sign(&KeyPair::create(), i))
+        .collect()
+}
+
+// all signatures by the same key
+fn signatures_one_key() -> Vec<(Signature, GE, Vec<u8>)> {
+    let keypair = KeyPair::create();
+    (0..SIGNATURES).map(|i| sign(&keypair, i)).collect()
+}
+
+// R is normalized to even y as in the aggregated flow, so batch_verify takes its fast path
+fn sign(keypair: &KeyPair, i: usize) -> (Signature, GE, Vec<u8>) {
+    let message = format!("message {}", i).into_bytes();
+    let mut ephemeral_key = EphemeralKey::create();
+    let (r, negated) =
+        EphemeralKey::aggregate_ephemeral_pub_keys(&[ephemeral_key.keypair.public_key]).unwrap();
+    if negated {
+        ephemeral_key.negate();
+    }
+    let c = EphemeralKey::hash_0(&r, &keypair.public_key, &message, false);
+    let s = EphemeralKey::sign(&ephemeral_key, &c, keypair, &BigInt::from(1));
+    let signature = EphemeralKey::add_signature_parts(s, &BigInt::from(0), &r).unwrap();
+    (signature, keypair.public_key, message)
+}
+
+fn bench_verify(c: &mut Criterion) {
+    let batch = signatures();
+    c.bench_function("Signature::verify 10k", move |b| {
+        b.iter(|| {
+            for (signature, pk, message) in &batch {
+                signature.verify(pk, message, false).unwrap();
+            }
+        })
+    });
+
+    let batch = signatures();
+    let verifier = Verifier::new();
+    c.bench_function("Verifier::verify 10k", move |b| {
+        b.iter(|| {
+            for (signature, pk, message) in &batch {
+                verifier.verify(signature, pk, message, false).unwrap();
+            }
+        })
+    });
+}
+
+fn bench_verify_one_key(c: &mut Criterion) {
+    let batch = signatures_one_key();
+    let verifier = Verifier::new();
+    c.bench_function("Verifier::verify 10k one key", move |b| {
+        b.iter(|| {
+            for (signature, pk, message) in &batch {
+                verifier.verify(signature, pk, message, false).unwrap();
+            }
+        })
+    });
+
+    let batch = signatures_one_key();
+    let verifier = CachedVerifier::new(batch[0].1);
+    c.bench_function("CachedVerifier::verify 10k one key", move |b| {
+        b.iter(|| {
+            for (signature, _, message) in &batch {
+                verifier.verify(signature, message, false).unwrap();
+            }
+        })
+    });
+}
+
+// batch_verify against a loop of Signature::verify over the same batch
+fn bench_batch_verify(c: &mut Criterion) {
+    let batch: Vec<(Signature, GE, Vec<u8>, bool)> = (0..BATCH_SIGNATURES)
+        .map(|i| {
+            let (signature, pk, message) = sign(&KeyPair::create(), i);
+            (signature, pk, message, false)
+        })
+        .collect();
+    let sequential_batch = batch.clone();
+    c.bench_function("Signature::verify 1k", move |b| {
+        b.iter(|| {
+            for (signature, pk, message, musig_bit) in &sequential_batch {
+                signature.verify(pk, message, *musig_bit).unwrap();
+            }
+        })
+    });
+    c.bench_function("batch_verify 1k", move |b| {
+        b.iter(|| batch_verify(&batch).unwrap())
+    });
+
+    let keypair = KeyPair::create();
+    let batch: Vec<(Signature, GE, Vec<u8>, bool)> = (0..BATCH_SIGNATURES)
+        .map(|i| {
+            let (signature, pk, message) = sign(&keypair, i);
+            (signature, pk, message, false)
+        })
+        .collect();
+    c.bench_function("batch_verify 1k one key", move |b| {
+        b.iter(|| batch_verify(&batch).unwrap())
+    });
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = bench_verify, bench_verify_one_key, bench_batch_verify
+}
+criterion_main!(benches);
@@ -0,0 +1,21 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use multi_party_schnorr::protocols::aggsig::Signature;
+
+// from_bytes must return Err instead of panicking, and whatever it accepts must encode back
+// to the same 64 bytes
+fuzz_target!(|data: &[u8]| {
+    if data.len() != 64 {
+        assert!(!Signature::is_valid_encoding(data));
+        return;
+    }
+    let mut bytes = [0u8; 64];
+    bytes.copy_from_slice(data);
+    match Signature::from_bytes(&bytes) {
+        Ok(signature) => {
+            assert!(Signature::is_valid_encoding(data));
+            assert_eq!(signature.to_bytes()[..], bytes[..]);
+        }
+        Err(_) => assert!(!Signature::is_valid_encoding(data)),
+    }
+});
@@ -0,0 +1,31 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use multi_party_schnorr::protocols::aggsig::{verify_bytes, ChallengeMode};
+
+// The input is read as
+//   mode (1 byte, bit 0 set for Musig) || apk length (1 byte)
+//   || r_x (32 bytes) || s (32 bytes) || apk || message
+// where every field takes what is left if the input is too short, so the lengths reaching
+// verify_bytes are arbitrary as well. No input may panic.
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 2 {
+        return;
+    }
+    let mode = if data[0] & 1 == 1 {
+        ChallengeMode::Musig
+    } else {
+        ChallengeMode::Plain
+    };
+    let apk_len = data[1] as usize;
+    let mut rest = &data[2..];
+    let r_x = take(&mut rest, 32);
+    let s = take(&mut rest, 32);
+    let apk = take(&mut rest, apk_len);
+    let _ = verify_bytes(s, r_x, apk, rest, mode);
+});
+
+fn take<'a>(rest: &mut &'a [u8], len: usize) -> &'a [u8] {
+    let (head, tail) = rest.split_at(len.min(rest.len()));
+    *rest = tail;
+    head
+}
@@ -20,6 +20,10 @@ extern crate serde;
 
 extern crate centipede;
 extern crate curv;
+extern crate rand;
+#[cfg(feature = "parallel")]
+extern crate rayon;
+extern crate sha2;
 pub mod protocols;
 
 #[derive(Copy, PartialEq, Eq, Clone, Debug)]
@@ -0,0 +1,124 @@
+/*
+    Multisig Schnorr
+
+    Copyright 2018 by Kzen Networks
+
+    This file is part of Multisig Schnorr library
+    (https://github.com/KZen-networks/multisig-schnorr)
+
+    Multisig Schnorr is free software: you can redistribute
+    it and/or modify it under the terms of the GNU General Public
+    License as published by the Free Software Foundation, either
+    version 3 of the License, or (at your option) any later version.
+
+    @license GPL-3.0+ <https://github.com/KZen-networks/multisig-schnorr/blob/master/LICENSE>
+*/
+
+//! Adaptor signatures: a signature encrypted to the secret t of an adaptor point T = t·G.
+//!
+//! The signer picks a nonce R = k·G and uses R + T in the challenge, c = hash_0(R + T, P, m).
+//! The adaptor signature s' = k + c·x verifies with s'·G = R + c·P against T, adding t gives
+//! the signature (s' + t, (R + T).x) that verifies with `Signature::verify`, and anyone
+//! holding both signatures learns t = s - s'.
+use curv::arithmetic::traits::Modulo;
+use curv::elliptic::curves::traits::*;
+use curv::{BigInt, FE, GE};
+
+use protocols::aggsig::{
+    compute_challenge_fe, curve_order, negate_point, ChallengeMode, EphemeralKey, KeyPair,
+    MuSigError, Signature,
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AdaptorSignature {
+    // the nonce R without the adaptor point
+    pub r: GE,
+    pub s: BigInt,
+}
+
+impl AdaptorSignature {
+    // R + T, the nonce of the completed signature. R = -T, which would put the sum at
+    // infinity, gives InvalidPoint.
+    fn r_tag(&self, t_point: &GE) -> Result<GE, MuSigError> {
+        if self.r == negate_point(t_point) {
+            return Err(MuSigError::InvalidPoint);
+        }
+        Ok(EphemeralKey::add_ephemeral_pub_keys(&self.r, t_point))
+    }
+}
+
+// s' must be in [1, q) like the s of a signature.
+fn check_s_prime(s_prime: &BigInt) -> Result<(), MuSigError> {
+    if *s_prime == BigInt::from(0) || *s_prime >= curve_order() {
+        return Err(MuSigError::InvalidScalar);
+    }
+    Ok(())
+}
+
+pub fn adaptor_sign(keypair: &KeyPair, t_point: &GE, message: &[u8]) -> AdaptorSignature {
+    let ephemeral_key = EphemeralKey::create();
+    let r = ephemeral_key.keypair.public_key;
+    let r_tag = EphemeralKey::add_ephemeral_pub_keys(&r, t_point);
+    let c = EphemeralKey::hash_0(&r_tag, &keypair.public_key, message, ChallengeMode::Plain);
+    let s = EphemeralKey::sign(&ephemeral_key, &c, keypair, &BigInt::from(1));
+    AdaptorSignature { r, s }
+}
+
+// s'·G == R + c·P with c = hash_0(R + T, P, m).
+pub fn adaptor_verify(
+    adaptor_signature: &AdaptorSignature,
+    public_key: &GE,
+    message: &[u8],
+    t_point: &GE,
+) -> Result<(), MuSigError> {
+    check_s_prime(&adaptor_signature.s)?;
+    let r_tag = adaptor_signature.r_tag(t_point)?;
+    let c = compute_challenge_fe(&r_tag, public_key, message, ChallengeMode::Plain);
+    let s: FE = ECScalar::from(&adaptor_signature.s);
+    let base_point: GE = ECPoint::generator();
+    if base_point * s == adaptor_signature.r + *public_key * c {
+        Ok(())
+    } else {
+        Err(MuSigError::InvalidSignature)
+    }
+}
+
+// (s' + t, (R + T).x). t must be non-zero mod q, t = 0 gives no adaptor point and is
+// InvalidScalar like an s' outside [1, q).
+pub fn adapt(adaptor_signature: &AdaptorSignature, t: &BigInt) -> Result<Signature, MuSigError> {
+    check_s_prime(&adaptor_signature.s)?;
+    if BigInt::modulus(t, &curve_order()) == BigInt::from(0) {
+        return Err(MuSigError::InvalidScalar);
+    }
+    let t_fe: FE = ECScalar::from(t);
+    let base_point: GE = ECPoint::generator();
+    let t_point = base_point * t_fe;
+    let s_prime: FE = ECScalar::from(&adaptor_signature.s);
+    let r_tag = adaptor_signature.r_tag(&t_point)?;
+    Ok(Signature {
+        s: (s_prime + t_fe).to_big_int(),
+        r_x: r_tag.x_coor().unwrap(),
+    })
+}
+
+// t = s - s'. The signature must be the adapted one, its r_x has to be the x of R + T
+// (InvalidSignature otherwise) and both s values must be below q (InvalidScalar).
+pub fn extract(
+    adaptor_signature: &AdaptorSignature,
+    signature: &Signature,
+    t_point: &GE,
+) -> Result<BigInt, MuSigError> {
+    check_s_prime(&adaptor_signature.s)?;
+    if signature.s >= curve_order() {
+        return Err(MuSigError::InvalidScalar);
+    }
+    let r_tag = adaptor_signature.r_tag(t_point)?;
+    if r_tag.x_coor().unwrap() != signature.r_x {
+        return Err(MuSigError::InvalidSignature);
+    }
+    Ok(BigInt::mod_sub(
+        &signature.s,
+        &adaptor_signature.s,
+        &curve_order(),
+    ))
+}
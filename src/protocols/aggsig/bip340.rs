@@ -0,0 +1,141 @@
+/*
+    Multisig Schnorr
+
+    Copyright 2018 by Kzen Networks
+
+    This file is part of Multisig Schnorr library
+    (https://github.com/KZen-networks/multisig-schnorr)
+
+    Multisig Schnorr is free software: you can redistribute
+    it and/or modify it under the terms of the GNU General Public
+    License as published by the Free Software Foundation, either
+    version 3 of the License, or (at your option) any later version.
+
+    @license GPL-3.0+ <https://github.com/KZen-networks/multisig-schnorr/blob/master/LICENSE>
+*/
+
+//! BIP340 compatible signing and verification.
+//!
+//! See https://github.com/bitcoin/bips/blob/master/bip-0340.mediawiki
+//!
+//! Public keys are 32 byte x-only keys: a key always stands for the point with even y. The
+//! challenge is the tagged hash H_tag("BIP0340/challenge", R_x || P_x || m) over the 32 byte
+//! encodings, and signing negates the private key and the nonce where needed so that both P
+//! and R have even y. The result is the 64 byte `Signature::to_bytes` encoding.
+use curv::elliptic::curves::traits::*;
+use curv::{BigInt, FE, GE};
+use sha2::{Digest, Sha256};
+
+use protocols::aggsig::{
+    constant_time_eq, curve_order, field_prime, lift_x_with_parity, negate_scalar, to_fixed_bytes,
+    KeyPair, MuSigError, Signature,
+};
+
+pub use protocols::aggsig::point_has_even_y as has_even_y;
+
+// SHA256(SHA256(tag) || SHA256(tag) || data)
+pub fn tagged_hash(tag: &str, data: &[&[u8]]) -> [u8; 32] {
+    let tag_hash = Sha256::digest(tag.as_bytes());
+    let mut hasher = Sha256::new();
+    hasher.input(&tag_hash);
+    hasher.input(&tag_hash);
+    for chunk in data {
+        hasher.input(chunk);
+    }
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(&hasher.result());
+    hash
+}
+
+// the 32 byte x-only encoding of a point
+pub fn xonly_bytes(point: &GE) -> [u8; 32] {
+    to_bytes32(&point.x_coor().unwrap())
+}
+
+// the point with x coordinate x and even y, None if x is not on the curve
+pub fn lift_x(x: &[u8; 32]) -> Option<GE> {
+    lift_x_with_parity(&BigInt::from(&x[..]), false)
+}
+
+// e = H_tag("BIP0340/challenge", R_x || P_x || m) mod q
+pub fn challenge(r_x: &[u8; 32], public_key: &[u8; 32], message: &[u8]) -> FE {
+    let e = tagged_hash("BIP0340/challenge", &[r_x, public_key, message]);
+    ECScalar::from(&BigInt::from(&e[..]))
+}
+
+pub fn sign(keypair: &KeyPair, message: &[u8], aux_rand: &[u8; 32]) -> Signature {
+    let public_key = xonly_bytes(&keypair.public_key);
+    let d = if has_even_y(&keypair.public_key) {
+        keypair.private_key.to_scalar()
+    } else {
+        negate_scalar(&keypair.private_key.to_scalar())
+    };
+
+    let aux_hash = tagged_hash("BIP0340/aux", &[aux_rand]);
+    let t: Vec<u8> = to_fixed_bytes(&d.to_big_int(), 32)
+        .iter()
+        .zip(aux_hash.iter())
+        .map(|(d_byte, aux_byte)| d_byte ^ aux_byte)
+        .collect();
+    let rand = tagged_hash("BIP0340/nonce", &[&t, &public_key, message]);
+    let k_prime: FE = ECScalar::from(&BigInt::from(&rand[..]));
+    assert!(k_prime.to_big_int() != BigInt::from(0));
+
+    let base_point: GE = ECPoint::generator();
+    let r = base_point * k_prime;
+    let k = if has_even_y(&r) {
+        k_prime
+    } else {
+        negate_scalar(&k_prime)
+    };
+    let r_x = xonly_bytes(&r);
+    let e = challenge(&r_x, &public_key, message);
+    let s = k + e * d;
+    Signature {
+        s: s.to_big_int(),
+        r_x: BigInt::from(&r_x[..]),
+    }
+}
+
+pub fn verify(
+    signature: &Signature,
+    public_key: &[u8; 32],
+    message: &[u8],
+) -> Result<(), MuSigError> {
+    let pk = match lift_x(public_key) {
+        Some(pk) => pk,
+        None => return Err(MuSigError::InvalidPoint),
+    };
+    if signature.r_x >= field_prime() {
+        return Err(MuSigError::InvalidPoint);
+    }
+    if signature.s >= curve_order() {
+        return Err(MuSigError::InvalidScalar);
+    }
+    let e = challenge(&to_bytes32(&signature.r_x), public_key, message);
+
+    let base_point: GE = ECPoint::generator();
+    let s: FE = ECScalar::from(&signature.s);
+    // s = 0 can not be multiplied with the base point and never gives a valid signature
+    if s.to_big_int() == BigInt::from(0) {
+        return Err(MuSigError::InvalidScalar);
+    }
+    let s_g = base_point * s;
+    let e_pk = pk * e;
+    // R = sG - eP is the point at infinity
+    if s_g == e_pk {
+        return Err(MuSigError::InvalidSignature);
+    }
+    let r = s_g.sub_point(&e_pk.get_element());
+    if has_even_y(&r) && constant_time_eq(&r.x_coor().unwrap(), &signature.r_x) {
+        Ok(())
+    } else {
+        Err(MuSigError::InvalidSignature)
+    }
+}
+
+fn to_bytes32(n: &BigInt) -> [u8; 32] {
+    let mut bytes = [0u8; 32];
+    bytes.copy_from_slice(&to_fixed_bytes(n, 32));
+    bytes
+}
@@ -0,0 +1,100 @@
+/*
+    Multisig Schnorr
+
+    Copyright 2018 by Kzen Networks
+
+    This file is part of Multisig Schnorr library
+    (https://github.com/KZen-networks/multisig-schnorr)
+
+    Multisig Schnorr is free software: you can redistribute
+    it and/or modify it under the terms of the GNU General Public
+    License as published by the Free Software Foundation, either
+    version 3 of the License, or (at your option) any later version.
+
+    @license GPL-3.0+ <https://github.com/KZen-networks/multisig-schnorr/blob/master/LICENSE>
+*/
+
+//! Blind Schnorr signatures: the signer signs a message it never sees.
+//!
+//! The signer with key X = x·G sends a nonce R = k·G. The user picks random α, β, computes
+//! R' = R + α·G + β·X and the challenge c' = hash_0(R', X, m), and sends the blinded challenge
+//! c = c' + β. The signer answers s = k + c·x and the user unblinds s' = s + α, so that
+//! s'·G = R' + c'·X and (s', R'.x) verifies with `Signature::verify` and `ChallengeMode::Plain`.
+//! c is uniformly random for the signer, independent of m.
+//!
+//! The plain scheme is only secure if a signer does not run many sessions concurrently (ROS
+//! attack), the nonce of a session must be used for one blinded challenge only.
+use curv::elliptic::curves::traits::*;
+use curv::{BigInt, FE, GE};
+
+use protocols::aggsig::{
+    compute_challenge_fe, random_scalar, with_default_rng, ChallengeMode, KeyPair, MuSigError,
+    Signature,
+};
+
+// the signer side of one session, consumed by sign so the nonce can not be reused
+#[derive(Debug)]
+pub struct SignerSession {
+    nonce: KeyPair,
+}
+
+impl SignerSession {
+    pub fn create() -> SignerSession {
+        SignerSession {
+            nonce: KeyPair::create(),
+        }
+    }
+
+    // R, sent to the user
+    pub fn public_nonce(&self) -> GE {
+        self.nonce.public_key
+    }
+
+    // s = k + c·x over the blinded challenge c
+    pub fn sign(self, keypair: &KeyPair, blinded_challenge: &BigInt) -> BigInt {
+        let c: FE = ECScalar::from(blinded_challenge);
+        let s = self.nonce.private_key.to_scalar() + c * keypair.private_key.to_scalar();
+        s.to_big_int()
+    }
+}
+
+// the user side of one session, the blinding factors are secret
+pub struct UserSession {
+    alpha: FE,
+    signer_pk: GE,
+    message: Vec<u8>,
+    r_prime: GE,
+    blinded_challenge: BigInt,
+}
+
+impl UserSession {
+    pub fn new(signer_pk: &GE, signer_nonce: &GE, message: &[u8]) -> UserSession {
+        let (alpha, beta) = with_default_rng(|rng| (random_scalar(rng), random_scalar(rng)));
+        let base_point: GE = ECPoint::generator();
+        let r_prime = *signer_nonce + base_point * alpha + *signer_pk * beta;
+        let c = compute_challenge_fe(&r_prime, signer_pk, message, ChallengeMode::Plain);
+        UserSession {
+            alpha,
+            signer_pk: *signer_pk,
+            message: message.to_vec(),
+            r_prime,
+            blinded_challenge: (c + beta).to_big_int(),
+        }
+    }
+
+    // c = c' + β, sent to the signer
+    pub fn blinded_challenge(&self) -> BigInt {
+        self.blinded_challenge.clone()
+    }
+
+    // s' = s + α, checked against the signer's key
+    pub fn unblind(self, s: &BigInt) -> Result<Signature, MuSigError> {
+        let s: FE = ECScalar::from(s);
+        let signature = Signature {
+            s: (s + self.alpha).to_big_int(),
+            r_x: self.r_prime.x_coor().unwrap(),
+        };
+        signature.verify(&self.signer_pk, &self.message, ChallengeMode::Plain)?;
+        Ok(signature)
+    }
+}
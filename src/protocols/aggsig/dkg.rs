@@ -0,0 +1,152 @@
+/*
+    Multisig Schnorr
+
+    Copyright 2018 by Kzen Networks
+
+    This file is part of Multisig Schnorr library
+    (https://github.com/KZen-networks/multisig-schnorr)
+
+    Multisig Schnorr is free software: you can redistribute
+    it and/or modify it under the terms of the GNU General Public
+    License as published by the Free Software Foundation, either
+    version 3 of the License, or (at your option) any later version.
+
+    @license GPL-3.0+ <https://github.com/KZen-networks/multisig-schnorr/blob/master/LICENSE>
+*/
+
+//! Interactive distributed key generation for the {t,n} signing of `threshold`.
+//!
+//! Every party picks a random secret and shares it with Feldman VSS, a polynomial of degree
+//! t - 1 whose coefficients are committed to as points. In `round1` a party only broadcasts a
+//! hash commitment to these points, so no party can pick its polynomial after seeing those of
+//! the others and bias the group key. In `round2` it opens the commitment and sends party j
+//! its share f(j + 1) over a private channel. `finalize` checks every opening and every
+//! received share and returns a `ThresholdKeyPair`: the group public key is the sum of the
+//! committed secrets times G and the local share is the sum of the received shares. The
+//! group private key is never known to any party.
+use curv::cryptographic_primitives::commitments::hash_commitment::HashCommitment;
+use curv::cryptographic_primitives::commitments::traits::Commitment;
+use curv::cryptographic_primitives::hashing::hash_sha256::HSha256;
+use curv::cryptographic_primitives::hashing::traits::Hash;
+use curv::cryptographic_primitives::secret_sharing::feldman_vss::VerifiableSS;
+use curv::elliptic::curves::traits::*;
+use curv::{BigInt, FE};
+
+use protocols::aggsig::threshold::{ThresholdKeyPair, ThresholdParams};
+use protocols::aggsig::{random_scalar, with_default_rng, CommitmentScheme, MuSigError};
+
+// round one broadcast: the commitment to the polynomial commitments of the sender
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Round1Message {
+    pub commitment: BigInt,
+}
+
+// round two broadcast: the polynomial commitments and the blind factor opening the round one
+// commitment. The shares are sent separately, each to its own party.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Round2Message {
+    pub vss: VerifiableSS,
+    pub blind_factor: BigInt,
+}
+
+// a party that sent its round one message. The shares are secret material, so unlike the
+// messages the state is not Debug.
+pub struct Round1State {
+    params: ThresholdParams,
+    party_index: usize,
+    vss: VerifiableSS,
+    shares: Vec<FE>,
+    blind_factor: BigInt,
+}
+
+// a party that opened its commitment and waits for the openings and shares of the others
+#[derive(Debug)]
+pub struct Round2State {
+    params: ThresholdParams,
+    party_index: usize,
+    commitments: Vec<BigInt>,
+}
+
+pub fn round1(params: &ThresholdParams, party_index: usize) -> (Round1State, Round1Message) {
+    assert!(params.t > 0 && params.t <= params.n);
+    assert!(party_index < params.n);
+    let (secret, blind_factor) = with_default_rng(|rng| {
+        (
+            random_scalar(rng),
+            <HashCommitment as CommitmentScheme>::sample_blind_factor(rng),
+        )
+    });
+    let (vss, shares) = VerifiableSS::share(params.t - 1, params.n, &secret);
+    let commitment = commit(&vss, &blind_factor);
+    let state = Round1State {
+        params: *params,
+        party_index,
+        vss,
+        shares,
+        blind_factor,
+    };
+    (state, Round1Message { commitment })
+}
+
+// received[j] is the round one message of party j. Returns the broadcast opening and the
+// shares, shares[j] to be sent to party j only.
+pub fn round2(
+    state: Round1State,
+    received: &[Round1Message],
+) -> Result<(Round2State, Round2Message, Vec<FE>), MuSigError> {
+    if received.len() != state.params.n {
+        return Err(MuSigError::InvalidEncoding);
+    }
+    let next = Round2State {
+        params: state.params,
+        party_index: state.party_index,
+        commitments: received.iter().map(|m| m.commitment.clone()).collect(),
+    };
+    let opening = Round2Message {
+        vss: state.vss,
+        blind_factor: state.blind_factor,
+    };
+    Ok((next, opening, state.shares))
+}
+
+// openings[j] and shares[j] are the round two message of party j and the share it sent to
+// this party. An opening that does not match the round one commitment, or a share that does
+// not match the polynomial commitments of its sender, gives CommitmentMismatch with the
+// index of the sender.
+pub fn finalize(
+    state: Round2State,
+    openings: &[Round2Message],
+    shares: &[FE],
+) -> Result<ThresholdKeyPair, MuSigError> {
+    let n = state.params.n;
+    if openings.len() != n || shares.len() != n {
+        return Err(MuSigError::InvalidEncoding);
+    }
+    for (j, (opening, share)) in openings.iter().zip(shares).enumerate() {
+        if opening.vss.commitments.len() != state.params.t {
+            return Err(MuSigError::InvalidEncoding);
+        }
+        if commit(&opening.vss, &opening.blind_factor) != state.commitments[j]
+            || opening
+                .vss
+                .validate_share(share, state.party_index + 1)
+                .is_err()
+        {
+            return Err(MuSigError::CommitmentMismatch { index: Some(j) });
+        }
+    }
+    let vss_vec: Vec<VerifiableSS> = openings.iter().map(|m| m.vss.clone()).collect();
+    ThresholdKeyPair::construct(&state.params, state.party_index, &vss_vec, shares)
+        .map_err(|_| MuSigError::CommitmentMismatch { index: None })
+}
+
+// hash commitment to the compressed polynomial commitments
+fn commit(vss: &VerifiableSS, blind_factor: &BigInt) -> BigInt {
+    let points: Vec<BigInt> = vss
+        .commitments
+        .iter()
+        .map(|point| point.bytes_compressed_to_big_int())
+        .collect();
+    let points_hash = HSha256::create_hash(&points.iter().collect::<Vec<&BigInt>>());
+    HashCommitment::create_commitment_with_user_defined_randomness(&points_hash, blind_factor)
+}
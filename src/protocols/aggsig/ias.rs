@@ -0,0 +1,119 @@
+/*
+    Multisig Schnorr
+
+    Copyright 2018 by Kzen Networks
+
+    This file is part of Multisig Schnorr library
+    (https://github.com/KZen-networks/multisig-schnorr)
+
+    Multisig Schnorr is free software: you can redistribute
+    it and/or modify it under the terms of the GNU General Public
+    License as published by the Free Software Foundation, either
+    version 3 of the License, or (at your option) any later version.
+
+    @license GPL-3.0+ <https://github.com/KZen-networks/multisig-schnorr/blob/master/LICENSE>
+*/
+
+//! Interactive aggregate signatures: n parties sign n distinct messages into one signature.
+//!
+//! The nonces are aggregated as in the {n,n} protocol, R' = sum of R_i negated to even y, see
+//! `EphemeralKey::aggregate_ephemeral_pub_keys`. Party i signs with its own challenge
+//! c_i = H("musig/ias", r_x, L, pk_i, m_i), where L = H(pk_1, m_1, ..., pk_n, m_n) commits to
+//! all pairs in order, so a share is only valid for this exact set of keys and messages and
+//! no key aggregation coefficients are needed. The share is s_i = k_i + c_i·x_i, i.e.
+//! `EphemeralKey::sign` with a = 1, and the aggregate s = sum of s_i verifies iff the x
+//! coordinate of s·G - sum of c_i·pk_i is r_x.
+use curv::cryptographic_primitives::hashing::traits::*;
+use curv::elliptic::curves::traits::*;
+use curv::{BigInt, FE, GE};
+
+use protocols::aggsig::{
+    constant_time_eq, curve_order, is_x_coor_on_curve, message_to_field_input, negate_point,
+    DefaultHash, MuSigError, Signature,
+};
+
+const IAS_TAG: &str = "musig/ias";
+
+// the challenge c_i of the party signing entries[index] under the aggregated nonce r_hat
+pub fn challenge(
+    r_hat: &GE,
+    entries: &[(GE, Vec<u8>)],
+    index: usize,
+) -> Result<BigInt, MuSigError> {
+    if index >= entries.len() {
+        return Err(MuSigError::IndexOutOfRange);
+    }
+    let key_messages = key_message_list(entries);
+    let (pk, message) = &entries[index];
+    Ok(party_challenge(
+        &r_hat.x_coor().unwrap(),
+        &key_messages,
+        pk,
+        message,
+    ))
+}
+
+// entries[i] is the key and the message of party i, in the order used for the challenges
+pub fn verify_distinct_messages(
+    sig: &Signature,
+    entries: &[(GE, Vec<u8>)],
+) -> Result<(), MuSigError> {
+    if entries.is_empty() {
+        return Err(MuSigError::EmptyKeySet);
+    }
+    if !is_x_coor_on_curve(&sig.r_x) {
+        return Err(MuSigError::InvalidPoint);
+    }
+    if sig.s == BigInt::from(0) || sig.s >= curve_order() {
+        return Err(MuSigError::InvalidScalar);
+    }
+    let key_messages = key_message_list(entries);
+    let mut sum: Option<GE> = None;
+    for (pk, message) in entries {
+        let c: FE = ECScalar::from(&party_challenge(&sig.r_x, &key_messages, pk, message));
+        let c_pk = *pk * c;
+        sum = match sum {
+            None => Some(c_pk),
+            Some(ref acc) if *acc == negate_point(&c_pk) => None,
+            Some(acc) => Some(acc + c_pk),
+        };
+    }
+    let s: FE = ECScalar::from(&sig.s);
+    let base_point: GE = ECPoint::generator();
+    let s_g = base_point * s;
+    let r = match sum {
+        // the sum of the c_i·pk_i cancelled out, R' = s·G
+        None => s_g,
+        Some(ref sum) if *sum == s_g => return Err(MuSigError::InvalidSignature),
+        Some(sum) => s_g.sub_point(&sum.get_element()),
+    };
+    match r.x_coor() {
+        Some(ref x_coor) if constant_time_eq(x_coor, &sig.r_x) => Ok(()),
+        _ => Err(MuSigError::InvalidSignature),
+    }
+}
+
+// L = H(pk_1, m_1, ..., pk_n, m_n)
+fn key_message_list(entries: &[(GE, Vec<u8>)]) -> BigInt {
+    let inputs: Vec<BigInt> = entries
+        .iter()
+        .flat_map(|(pk, message)| {
+            vec![
+                pk.bytes_compressed_to_big_int(),
+                message_to_field_input(message),
+            ]
+        })
+        .collect();
+    DefaultHash::create_hash(&inputs.iter().collect::<Vec<&BigInt>>())
+}
+
+// c_i = H("musig/ias", r_x, L, pk_i, m_i)
+fn party_challenge(r_x: &BigInt, key_messages: &BigInt, pk: &GE, message: &[u8]) -> BigInt {
+    DefaultHash::create_hash(&[
+        &BigInt::from(IAS_TAG.as_bytes()),
+        r_x,
+        key_messages,
+        &pk.bytes_compressed_to_big_int(),
+        &message_to_field_input(message),
+    ])
+}
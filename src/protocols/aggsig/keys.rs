@@ -0,0 +1,140 @@
+/*
+    Multisig Schnorr
+
+    Copyright 2018 by Kzen Networks
+
+    This file is part of Multisig Schnorr library
+    (https://github.com/KZen-networks/multisig-schnorr)
+
+    Multisig Schnorr is free software: you can redistribute
+    it and/or modify it under the terms of the GNU General Public
+    License as published by the Free Software Foundation, either
+    version 3 of the License, or (at your option) any later version.
+
+    @license GPL-3.0+ <https://github.com/KZen-networks/multisig-schnorr/blob/master/LICENSE>
+*/
+
+//! Validated key types.
+//!
+//! A `PrivateKey` is a scalar in [1, q) and a `PublicKey` is a point on secp256k1 other than
+//! the point at infinity. The raw `FE`/`GE` values are available through `From`/`Into`.
+use curv::arithmetic::traits::Modulo;
+use curv::elliptic::curves::traits::*;
+use curv::{BigInt, FE, GE, PK};
+
+use protocols::aggsig::{curve_order, field_prime, point_from_compressed, MuSigError};
+
+use std::fmt;
+use std::ptr;
+use std::sync::atomic;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PublicKey(GE);
+
+impl PublicKey {
+    pub fn from_point(point: GE) -> Result<PublicKey, MuSigError> {
+        match (point.x_coor(), point.y_coor()) {
+            (Some(x), Some(y)) => PublicKey::from_coor(&x, &y),
+            _ => Err(MuSigError::InvalidPoint),
+        }
+    }
+
+    // checks y^2 = x^3 + 7 mod p before building the point
+    pub fn from_coor(x: &BigInt, y: &BigInt) -> Result<PublicKey, MuSigError> {
+        let p = field_prime();
+        let zero = BigInt::from(0);
+        if *x < zero || *x >= p || *y < zero || *y >= p {
+            return Err(MuSigError::InvalidPoint);
+        }
+        let y_square = BigInt::mod_mul(y, y, &p);
+        let x_cube_plus_7 = BigInt::mod_add(
+            &BigInt::mod_pow(x, &BigInt::from(3), &p),
+            &BigInt::from(7),
+            &p,
+        );
+        if y_square != x_cube_plus_7 {
+            return Err(MuSigError::InvalidPoint);
+        }
+        Ok(PublicKey(GE::from_coor(x, y)))
+    }
+
+    // 33 byte compressed or 65 byte uncompressed SEC1 encoding
+    pub fn from_bytes(bytes: &[u8]) -> Result<PublicKey, MuSigError> {
+        match bytes.len() {
+            33 => match point_from_compressed(bytes) {
+                Some(point) => Ok(PublicKey(point)),
+                None => Err(MuSigError::InvalidPoint),
+            },
+            65 if bytes[0] == 4 => {
+                PublicKey::from_coor(&BigInt::from(&bytes[1..33]), &BigInt::from(&bytes[33..]))
+            }
+            _ => Err(MuSigError::InvalidEncoding),
+        }
+    }
+
+    pub fn as_point(&self) -> &GE {
+        &self.0
+    }
+
+    pub fn from_pk(pk: &PK) -> Result<PublicKey, MuSigError> {
+        PublicKey::from_bytes(&pk.serialize())
+    }
+
+    pub fn to_pk(&self) -> PK {
+        self.0.get_element()
+    }
+}
+
+impl From<PublicKey> for GE {
+    fn from(public_key: PublicKey) -> GE {
+        public_key.0
+    }
+}
+
+// the scalar is overwritten when a PrivateKey is dropped, but not copies of it made while
+// signing
+#[derive(Serialize, Deserialize)]
+pub struct PrivateKey(FE);
+
+impl fmt::Debug for PrivateKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PrivateKey(REDACTED)")
+    }
+}
+
+impl PrivateKey {
+    // the value must be in [1, q), it is not reduced modulo q
+    pub fn from_big_int(private_key: &BigInt) -> Result<PrivateKey, MuSigError> {
+        if *private_key <= BigInt::from(0) || *private_key >= curve_order() {
+            return Err(MuSigError::InvalidScalar);
+        }
+        Ok(PrivateKey(ECScalar::from(private_key)))
+    }
+
+    pub fn from_scalar(private_key: FE) -> Result<PrivateKey, MuSigError> {
+        PrivateKey::from_big_int(&private_key.to_big_int())
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        let base_point: GE = ECPoint::generator();
+        PublicKey(base_point.scalar_mul(&self.0.get_element()))
+    }
+
+    // the raw scalar, secret material
+    pub fn to_scalar(&self) -> FE {
+        self.0
+    }
+}
+
+impl From<PrivateKey> for FE {
+    fn from(private_key: PrivateKey) -> FE {
+        private_key.0
+    }
+}
+
+impl Drop for PrivateKey {
+    fn drop(&mut self) {
+        unsafe { ptr::write_volatile(&mut self.0, FE::zero()) };
+        atomic::compiler_fence(atomic::Ordering::SeqCst);
+    }
+}
@@ -18,7 +18,11 @@
 //! aggregated Schnorr {n,n}-Signatures
 //!
 //! See https://eprint.iacr.org/2018/068.pdf, https://eprint.iacr.org/2018/483.pdf subsection 5.1
-use curv::{BigInt, FE, GE};
+//!
+//! The protocol is implemented over secp256k1 (`curv::{FE, GE}`). It is not generic over the
+//! curve: the curv `ECPoint<PK, SK>`/`ECScalar<SK>` traits carry no associated scalar type,
+//! and verification checks that r_x is the x coordinate of a secp256k1 point.
+use curv::{BigInt, FE, GE, PK, SK};
 
 use curv::cryptographic_primitives::proofs::*;
 use curv::elliptic::curves::traits::*;
@@ -26,246 +30,2493 @@ use curv::elliptic::curves::traits::*;
 use curv::cryptographic_primitives::hashing::hash_sha256::HSha256;
 use curv::cryptographic_primitives::hashing::traits::*;
 
-use curv::arithmetic::traits::Converter;
+use curv::arithmetic::traits::{Converter, Modulo};
 use curv::cryptographic_primitives::commitments::hash_commitment::HashCommitment;
+use curv::cryptographic_primitives::commitments::pedersen_commitment::PedersenCommitment;
 use curv::cryptographic_primitives::commitments::traits::*;
 
-#[derive(Debug)]
+use protocols::aggsig::keys::{PrivateKey, PublicKey};
+use rand::rngs::{OsRng, StdRng};
+use rand::{CryptoRng, RngCore};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// the serialized form of a KeyPair contains the private key: it is meant for persisting
+// signing state between rounds and must be stored as secret material. The private key is
+// wiped when the KeyPair is dropped, see PrivateKey.
+#[derive(Serialize, Deserialize)]
 pub struct KeyPair {
     pub public_key: GE,
-    private_key: FE,
+    private_key: PrivateKey,
 }
 
 impl KeyPair {
     pub fn create() -> KeyPair {
-        let ec_point: GE = ECPoint::generator();
-        let private_key: FE = ECScalar::new_random();
-        let public_key = ec_point.scalar_mul(&private_key.get_element());
-        KeyPair {
-            public_key,
+        with_default_rng(KeyPair::create_with_rng)
+    }
+
+    pub fn create_with_rng<R: RngCore + CryptoRng>(rng: &mut R) -> KeyPair {
+        // random_scalar is never zero
+        KeyPair::from_secret_scalar(random_scalar(rng)).unwrap()
+    }
+
+    // the private key must be in [1, q), it is not reduced modulo q. The public key is computed
+    // with curv's scalar_mul, which makes no promise to run in time independent of the key.
+    pub fn create_from_private_key(private_key: &BigInt) -> Result<KeyPair, MuSigError> {
+        Ok(KeyPair::from_private_key(PrivateKey::from_big_int(
             private_key,
-        }
+        )?))
+    }
+
+    // imports a raw private key, e.g. handed over from a hardware module. Zero gives
+    // InvalidScalar, like create_from_private_key.
+    pub fn from_secret_scalar(private_key: FE) -> Result<KeyPair, MuSigError> {
+        Ok(KeyPair::from_private_key(PrivateKey::from_scalar(
+            private_key,
+        )?))
     }
 
-    pub fn create_from_private_key(private_key: &BigInt) -> KeyPair {
-        let ec_point: GE = ECPoint::generator();
-        let private_key: FE = ECScalar::from(private_key);
-        let public_key = ec_point.scalar_mul(&private_key.get_element());
+    pub fn from_private_key(private_key: PrivateKey) -> KeyPair {
         KeyPair {
-            public_key,
+            public_key: private_key.public_key().into(),
             private_key,
         }
     }
+
+    // exports the private key for backup. The returned value is secret material and the
+    // caller is responsible for storing and wiping it securely.
+    pub fn expose_secret(&self) -> BigInt {
+        self.private_key.to_scalar().to_big_int()
+    }
+
+    // the 33 byte compressed SEC1 encoding of the public key
+    pub fn public_key_bytes(&self) -> Vec<u8> {
+        compressed_bytes(&self.public_key)
+    }
+
+    // the keys as the secp256k1 PK/SK types curv and the other KZen crates work with
+    pub fn to_pk(&self) -> PK {
+        self.public_key.get_element()
+    }
+
+    // secret material, like expose_secret
+    pub fn to_sk(&self) -> SK {
+        self.private_key.to_scalar().get_element()
+    }
+
+    // a Schnorr signature by the key over its own compressed encoding. Checking one proof
+    // per key before aggregation rules out rogue keys chosen as a function of the others.
+    pub fn prove_possession(&self) -> PopProof {
+        PopProof {
+            signature: self.sign_single(&pop_message(&self.public_key)),
+        }
+    }
+
+    // a single signer signature with a fresh nonce, verified with Signature::verify over
+    // public_key and ChallengeMode::Plain
+    pub fn sign_single<T: AsRef<[u8]>>(&self, message: T) -> Signature {
+        let nonce = with_default_rng(KeyPair::create_with_rng);
+        let r_x = nonce.public_key.x_coor().unwrap();
+        let c: FE = ECScalar::from(&compute_challenge(
+            &r_x,
+            &self.public_key,
+            message.as_ref(),
+            ChallengeMode::Plain,
+        ));
+        let s = nonce.private_key.to_scalar() + c * self.private_key.to_scalar();
+        Signature {
+            s: s.to_big_int(),
+            r_x,
+        }
+    }
+
+    // Deterministic key j from a wallet seed, e.g. the 64 byte BIP39 seed of a mnemonic. The
+    // private key is the first t_i = HMAC-SHA256(seed, "musig/seed" || index || i) in [1, q)
+    // for i = 0, 1, ..., with index a 4 byte and i a 1 byte big endian integer. This is not
+    // BIP32, the keys differ from those of a BIP32 wallet on the same seed.
+    pub fn from_seed(seed: &[u8], derivation_index: u32) -> KeyPair {
+        let q = curve_order();
+        let mut data = SEED_TAG.as_bytes().to_vec();
+        data.extend_from_slice(&derivation_index.to_be_bytes());
+        data.push(0);
+        loop {
+            let candidate = BigInt::from(&hmac_sha256(seed, &data)[..]);
+            if candidate != BigInt::from(0) && candidate < q {
+                return KeyPair::from_secret_scalar(ECScalar::from(&candidate)).unwrap();
+            }
+            // a candidate is out of range with probability below 2^-127
+            *data.last_mut().unwrap() += 1;
+        }
+    }
+
+    pub fn from_sk(sk: SK) -> Result<KeyPair, MuSigError> {
+        let mut private_key: FE = ECScalar::zero();
+        private_key.set_element(sk);
+        Ok(KeyPair::from_private_key(PrivateKey::from_scalar(
+            private_key,
+        )?))
+    }
+}
+
+// the private key is never printed, use expose_secret to get it
+impl fmt::Debug for KeyPair {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("KeyPair")
+            .field("public_key", &self.public_key)
+            .field("private_key", &"REDACTED")
+            .finish()
+    }
 }
 
-#[derive(Debug)]
+// apk always has even y. If the sum of the weighted keys had odd y it is negated, negated is
+// set and hash is the negated coefficient, so signing with hash needs no further adjustment.
+// tweak is the sum of the tweaks added with add_tweak, negated along with apk, so that
+// apk = sum of a_i·pk_i + tweak·G; it is zero for an untweaked key.
+#[derive(Debug, Serialize, Deserialize)]
 pub struct KeyAgg {
     pub apk: GE,
     pub hash: BigInt,
+    #[serde(default)]
+    pub negated: bool,
+    #[serde(default = "zero_tweak")]
+    pub tweak: BigInt,
 }
 
 impl KeyAgg {
+    // two party key aggregation, the hash is the coefficient of my_pk. It can not fail, so
+    // my_pk == other_pk is not rejected.
     pub fn key_aggregation(my_pk: &GE, other_pk: &GE) -> KeyAgg {
-        let hash = HSha256::create_hash(&[
-            &BigInt::from(1),
-            &my_pk.bytes_compressed_to_big_int(),
-            &my_pk.bytes_compressed_to_big_int(),
-            &other_pk.bytes_compressed_to_big_int(),
-        ]);
-        let hash_fe: FE = ECScalar::from(&hash);
-        let a1 = my_pk.scalar_mul(&hash_fe.get_element());
-
-        let hash2 = HSha256::create_hash(&[
-            &BigInt::from(1),
-            &other_pk.bytes_compressed_to_big_int(),
-            &my_pk.bytes_compressed_to_big_int(),
-            &other_pk.bytes_compressed_to_big_int(),
-        ]);
-        let hash2_fe: FE = ECScalar::from(&hash2);
-        let a2 = other_pk.scalar_mul(&hash2_fe.get_element());
-        let apk = a2.add_point(&(a1.get_element()));
-        KeyAgg { apk, hash }
+        let pks = [*my_pk, *other_pk];
+        let party_index = KeyAgg::sorted_position(&pks, my_pk).unwrap();
+        KeyAgg::key_aggregation_n_with_options(&pks, party_index, true).unwrap()
     }
 
-    pub fn key_aggregation_n(pks: &[GE], party_index: usize) -> KeyAgg {
-        let bn_1 = BigInt::from(1);
-        let x_coor_vec: Vec<BigInt> = pks
-            .iter()
-            .map(|pk| pk.bytes_compressed_to_big_int())
-            .collect();
+    // The keys are sorted by their compressed encoding before hashing, so every ordering of
+    // the same key set gives the same apk. party_index is the position of the local key in
+    // the sorted list, see sorted_position. A key that appears twice is almost always a bug
+    // and gives DuplicateKey, see key_aggregation_n_with_options.
+    pub fn key_aggregation_n(pks: &[GE], party_index: usize) -> Result<KeyAgg, MuSigError> {
+        KeyAgg::key_aggregation_iter(pks.iter().cloned(), party_index)
+    }
 
-        let hash_vec: Vec<BigInt> = x_coor_vec
-            .iter()
-            .map(|pk| {
-                let mut vec = Vec::new();
-                vec.push(&bn_1);
-                vec.push(pk);
-                for mpz in x_coor_vec.iter().take(pks.len()) {
-                    vec.push(mpz);
-                }
-                HSha256::create_hash(&vec)
-            })
-            .collect();
+    // key_aggregation_n that accepts repeated keys if allow_duplicates is set. Every copy of
+    // a key gets the same coefficient and counts once more in apk.
+    pub fn key_aggregation_n_with_options(
+        pks: &[GE],
+        party_index: usize,
+        allow_duplicates: bool,
+    ) -> Result<KeyAgg, MuSigError> {
+        let mut builder = KeyAggBuilder::new();
+        for pk in pks {
+            builder.add_key(pk);
+        }
+        KeyAgg::check_key_list(&builder, party_index, allow_duplicates)?;
+        Ok(builder.finalize(party_index))
+    }
 
-        let mut apk_vec: Vec<GE> = pks
-            .iter()
-            .zip(&hash_vec)
-            .map(|(pk, hash)| {
-                let hash_t: FE = ECScalar::from(&hash);
-                let pki: GE = pk.clone();
-                pki.scalar_mul(&hash_t.get_element())
+    // key_aggregation_n with the coefficients hashed by H instead of DefaultHash
+    pub fn key_aggregation_n_with_hash<H: Hash>(
+        pks: &[GE],
+        party_index: usize,
+    ) -> Result<KeyAgg, MuSigError> {
+        let mut builder = KeyAggBuilder::new();
+        for pk in pks {
+            builder.add_key(pk);
+        }
+        KeyAgg::check_key_list(&builder, party_index, false)?;
+        Ok(builder.finalize_with_hash::<H>(party_index))
+    }
+
+    // key_aggregation_n over keys from any iterator, e.g. the values of a map, without
+    // collecting them first
+    pub fn key_aggregation_iter<I: IntoIterator<Item = GE>>(
+        pks: I,
+        party_index: usize,
+    ) -> Result<KeyAgg, MuSigError> {
+        let mut builder = KeyAggBuilder::new();
+        for pk in pks {
+            builder.add_key(&pk);
+        }
+        KeyAgg::check_key_list(&builder, party_index, false)?;
+        Ok(builder.finalize(party_index))
+    }
+
+    fn check_key_list(
+        builder: &KeyAggBuilder,
+        party_index: usize,
+        allow_duplicates: bool,
+    ) -> Result<(), MuSigError> {
+        if builder.is_empty() {
+            return Err(MuSigError::EmptyKeySet);
+        }
+        if party_index >= builder.len() {
+            return Err(MuSigError::IndexOutOfRange);
+        }
+        if !allow_duplicates && builder.has_duplicate_keys() {
+            return Err(MuSigError::DuplicateKey);
+        }
+        Ok(())
+    }
+
+    // key_aggregation_n that also keeps the sorted key list, so the members can be listed and
+    // checked later. apk alone does not reveal which keys it was aggregated from.
+    pub fn with_members(pks: &[GE], party_index: usize) -> Result<KeyAggMembers, MuSigError> {
+        let key_agg = KeyAgg::key_aggregation_n(pks, party_index)?;
+        let mut members = pks.to_vec();
+        members.sort_by_key(|pk| pk.bytes_compressed_to_big_int());
+        Ok(KeyAggMembers { key_agg, members })
+    }
+
+    // key_aggregation_n after checking pops[i] for pks[i]
+    pub fn key_aggregation_with_pops(
+        pks: &[GE],
+        pops: &[PopProof],
+        party_index: usize,
+    ) -> Result<KeyAgg, MuSigError> {
+        if pks.len() != pops.len() {
+            return Err(MuSigError::IndexOutOfRange);
+        }
+        for (pk, pop) in pks.iter().zip(pops) {
+            verify_possession(pk, pop)?;
+        }
+        KeyAgg::key_aggregation_n(pks, party_index)
+    }
+
+    // Key aggregation with a voting weight w_i in [0, q) for pks[i]: apk is the sum of
+    // w_i·a_i·pk_i and hash is w_i·a_i, so partial signing with hash needs no change. Keys of
+    // weight 0 are dropped before the coefficients are computed, apk is the same as without
+    // them and their hash is 0. With every weight 1 this is key_aggregation_n.
+    pub fn key_aggregation_weighted(
+        pks: &[GE],
+        weights: &[BigInt],
+        party_index: usize,
+    ) -> Result<KeyAgg, MuSigError> {
+        if pks.len() != weights.len() || party_index >= pks.len() {
+            return Err(MuSigError::IndexOutOfRange);
+        }
+        if !weights.iter().all(is_reduced_scalar) {
+            return Err(MuSigError::InvalidScalar);
+        }
+        let zero = BigInt::from(0);
+        let mut sorted: Vec<(&GE, &BigInt)> = pks.iter().zip(weights).collect();
+        sorted.sort_by_key(|(pk, _)| pk.bytes_compressed_to_big_int());
+        let mut builder = KeyAggBuilder::new();
+        let mut key_weights = Vec::new();
+        for (pk, weight) in sorted.iter().filter(|(_, weight)| **weight != zero) {
+            builder.add_key(pk);
+            key_weights.push(*weight);
+        }
+        if builder.is_empty() {
+            return Err(MuSigError::EmptyKeySet);
+        }
+        if builder.has_duplicate_keys() {
+            return Err(MuSigError::DuplicateKey);
+        }
+        let weighted_keys = builder
+            .coefficients::<DefaultHash>()
+            .into_iter()
+            .zip(key_weights)
+            .map(|((hash, weighted_pk), weight)| {
+                let w: FE = ECScalar::from(weight);
+                let a: FE = ECScalar::from(&hash);
+                ((a * w).to_big_int(), weighted_pk * w)
             })
             .collect();
+        let (apk, mut hash_vec, negated) = KeyAggBuilder::sum_weighted_keys(weighted_keys);
+        let hash = if *sorted[party_index].1 == zero {
+            zero
+        } else {
+            let position = sorted[..party_index]
+                .iter()
+                .filter(|(_, weight)| **weight != zero)
+                .count();
+            hash_vec.swap_remove(position)
+        };
+        Ok(KeyAgg {
+            apk,
+            hash,
+            negated,
+            tweak: zero_tweak(),
+        })
+    }
 
-        let pk1 = apk_vec.remove(0);
-        let sum = apk_vec
-            .iter()
-            .fold(pk1, |acc, pk| acc.add_point(&pk.get_element()));
+    // Aggregation of the apks of subgroups as leaf keys, for a group of groups. index is the
+    // sorted position of the subgroup, as in key_aggregation_n. A member i of subgroup j
+    // contributes a_j·a_ij·pk_i to the top level apk, where a_j is the hash of this KeyAgg and
+    // a_ij the hash of the member's KeyAgg in its subgroup, both negated with their apk if
+    // needed. See with_member for the KeyAgg a member signs with.
+    pub fn aggregate_subgroups(subgroup_apks: &[GE], index: usize) -> Result<KeyAgg, MuSigError> {
+        KeyAgg::key_aggregation_n(subgroup_apks, index)
+    }
 
+    // the KeyAgg of a subgroup member at the top level of aggregate_subgroups: the top level
+    // apk and the coefficient a_j·a_ij. All members of all subgroups aggregate their nonces
+    // together and sign the challenge over the top level apk with this hash.
+    pub fn with_member(&self, member: &KeyAgg) -> KeyAgg {
+        let a_j: FE = ECScalar::from(&self.hash);
+        let a_ij: FE = ECScalar::from(&member.hash);
         KeyAgg {
-            apk: sum,
-            hash: hash_vec[party_index].clone(),
+            apk: self.apk,
+            hash: (a_j * a_ij).to_big_int(),
+            negated: self.negated,
+            tweak: self.tweak.clone(),
+        }
+    }
+
+    // the coefficient a_i of the key at party_index in the sorted list, negated like hash if
+    // apk was negated, so the sum of a_i·pk_i is apk
+    pub fn coefficient(pks: &[GE], party_index: usize) -> Result<BigInt, MuSigError> {
+        Ok(KeyAgg::key_aggregation_n(pks, party_index)?.hash)
+    }
+
+    // the 33 byte compressed SEC1 encoding of apk
+    pub fn apk_bytes(&self) -> Vec<u8> {
+        compressed_bytes(&self.apk)
+    }
+
+    // the 65 byte uncompressed SEC1 encoding 04 || x || y of apk, for verifiers that do not
+    // take compressed keys
+    pub fn apk_uncompressed_bytes(&self) -> [u8; 65] {
+        let mut bytes = [0u8; 65];
+        bytes[0] = 4;
+        bytes[1..33].copy_from_slice(&to_fixed_bytes(&self.apk.x_coor().unwrap(), 32));
+        bytes[33..].copy_from_slice(&to_fixed_bytes(&self.apk.y_coor().unwrap(), 32));
+        bytes
+    }
+
+    // 33 byte compressed apk, 32 byte hash and one byte for negated, hex encoded, followed by
+    // the 32 byte tweak if it is not zero
+    pub fn to_hex(&self) -> String {
+        let mut bytes = self.apk_bytes();
+        bytes.extend_from_slice(&to_fixed_bytes(&self.hash, 32));
+        bytes.push(self.negated as u8);
+        if self.tweak != zero_tweak() {
+            bytes.extend_from_slice(&to_fixed_bytes(&self.tweak, 32));
+        }
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    pub fn from_hex(hex: &str) -> Result<KeyAgg, MuSigError> {
+        if (hex.len() != 2 * KEY_AGG_BYTES && hex.len() != 2 * (KEY_AGG_BYTES + 32))
+            || !hex.chars().all(|c| c.is_ascii_hexdigit())
+        {
+            return Err(MuSigError::InvalidEncoding);
+        }
+        let apk_bytes = BigInt::to_vec(&BigInt::from_str_radix(&hex[..66], 16).unwrap());
+        let apk = match point_from_compressed(&apk_bytes) {
+            Some(apk) => apk,
+            None => return Err(MuSigError::InvalidPoint),
+        };
+        let hash = BigInt::from_str_radix(&hex[66..130], 16).unwrap();
+        let negated = match &hex[130..132] {
+            "00" => false,
+            "01" => true,
+            _ => return Err(MuSigError::InvalidEncoding),
+        };
+        let tweak = if hex.len() > 132 {
+            let tweak = BigInt::from_str_radix(&hex[132..], 16).unwrap();
+            if tweak == zero_tweak() || tweak >= curve_order() {
+                return Err(MuSigError::InvalidScalar);
+            }
+            tweak
+        } else {
+            zero_tweak()
+        };
+        Ok(KeyAgg {
+            apk,
+            hash,
+            negated,
+            tweak,
+        })
+    }
+
+    // apk' = apk + t·G, negated to even y like apk. If it is negated, hash and the tweak sum
+    // are negated too and negated is flipped. Signatures under apk' need one designated party
+    // to sign with EphemeralKey::sign_with_tweak and the tweak of the result, all others sign
+    // as usual.
+    pub fn add_tweak(&self, t: &BigInt) -> KeyAgg {
+        let q = curve_order();
+        let t = BigInt::mod_add(t, &zero_tweak(), &q);
+        let apk = if t == zero_tweak() {
+            self.apk
+        } else {
+            let base_point: GE = ECPoint::generator();
+            let t: FE = ECScalar::from(&t);
+            self.apk + base_point * t
+        };
+        let tweak = BigInt::mod_add(&self.tweak, &t, &q);
+        if point_has_even_y(&apk) {
+            KeyAgg {
+                apk,
+                hash: self.hash.clone(),
+                negated: self.negated,
+                tweak,
+            }
+        } else {
+            KeyAgg {
+                apk: negate_point(&apk),
+                hash: BigInt::mod_sub(&zero_tweak(), &self.hash, &q),
+                negated: !self.negated,
+                tweak: BigInt::mod_sub(&zero_tweak(), &tweak, &q),
+            }
+        }
+    }
+
+    // the aggregated public key only, for parties that do not sign with one of the keys. The
+    // key list is checked like in key_aggregation_n, so an empty list gives EmptyKeySet and a
+    // repeated key DuplicateKey: an observer gets no apk that the signers would refuse.
+    pub fn aggregate_only(pks: &[GE]) -> Result<GE, MuSigError> {
+        let mut builder = KeyAggBuilder::new();
+        for pk in pks {
+            builder.add_key(pk);
+        }
+        KeyAgg::check_key_list(&builder, 0, false)?;
+        Ok(builder.compute::<DefaultHash>().0)
+    }
+
+    // position of pk in the sorted key list used by key_aggregation_n
+    pub fn sorted_position(pks: &[GE], pk: &GE) -> Option<usize> {
+        let encoded_pk = pk.bytes_compressed_to_big_int();
+        if !pks
+            .iter()
+            .any(|other| other.bytes_compressed_to_big_int() == encoded_pk)
+        {
+            return None;
         }
+        Some(
+            pks.iter()
+                .filter(|other| other.bytes_compressed_to_big_int() < encoded_pk)
+                .count(),
+        )
     }
 }
 
-#[derive(Debug)]
-pub struct EphemeralKey {
-    pub keypair: KeyPair,
-    pub commitment: BigInt,
-    pub blind_factor: BigInt,
+// Two KeyAggs are equal if apk, the coefficient hash and the tweak are. The tweak decides
+// what the designated party adds in sign_with_tweak, so KeyAggs that differ in it do not sign
+// alike even if apk and hash agree. negated follows from the key set and the tweak and is not
+// compared. The Hash covers apk only: the results for the different parties of one key set
+// differ in hash but land in the same bucket, so a map keyed by KeyAgg still holds one entry
+// per party and lookups stay consistent with Eq.
+impl PartialEq for KeyAgg {
+    fn eq(&self, other: &KeyAgg) -> bool {
+        self.apk == other.apk && self.hash == other.hash && self.tweak == other.tweak
+    }
 }
 
-impl EphemeralKey {
-    pub fn create() -> EphemeralKey {
-        let keypair = KeyPair::create();
-        let (commitment, blind_factor) =
-            HashCommitment::create_commitment(&keypair.public_key.bytes_compressed_to_big_int());
-        EphemeralKey {
-            keypair,
-            commitment,
-            blind_factor,
+impl Eq for KeyAgg {}
+
+impl std::hash::Hash for KeyAgg {
+    fn hash<S: std::hash::Hasher>(&self, state: &mut S) {
+        std::hash::Hash::hash(&self.apk_bytes(), state);
+    }
+}
+
+// a KeyAgg together with the keys aggregated into apk, sorted like in key_aggregation_n
+#[derive(Debug, Serialize, Deserialize)]
+pub struct KeyAggMembers {
+    pub key_agg: KeyAgg,
+    members: Vec<GE>,
+}
+
+impl KeyAggMembers {
+    pub fn contains(&self, pk: &GE) -> bool {
+        let encoded_pk = pk.bytes_compressed_to_big_int();
+        self.members
+            .binary_search_by_key(&encoded_pk, |member| member.bytes_compressed_to_big_int())
+            .is_ok()
+    }
+
+    pub fn members(&self) -> &[GE] {
+        &self.members
+    }
+}
+
+// The key list of a signing group, sorted once, and the positions of its members as
+// SignerHandles. Every set gets its own id and a handle only works with the set that issued
+// it, so a position can not be taken from one key list and used with another.
+#[derive(Debug, Clone)]
+pub struct SignerSet {
+    id: usize,
+    pks: Vec<GE>,
+}
+
+// a member of a SignerSet, see SignerSet::handle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SignerHandle {
+    set_id: usize,
+    index: usize,
+}
+
+static NEXT_SIGNER_SET_ID: AtomicUsize = AtomicUsize::new(0);
+
+impl SignerSet {
+    // the same checks as key_aggregation_n: no empty list and no repeated keys
+    pub fn new(pks: &[GE]) -> Result<SignerSet, MuSigError> {
+        let mut builder = KeyAggBuilder::new();
+        for pk in pks {
+            builder.add_key(pk);
         }
+        KeyAgg::check_key_list(&builder, 0, false)?;
+        Ok(SignerSet {
+            id: NEXT_SIGNER_SET_ID.fetch_add(1, Ordering::Relaxed),
+            pks: builder.pks,
+        })
     }
 
-    pub fn create_from_private_key(x1: &KeyPair, message: &[u8]) -> EphemeralKey {
-        let base_point: GE = ECPoint::generator();
-        let hash_private_key_message =
-            HSha256::create_hash(&[&x1.private_key.to_big_int(), &BigInt::from(message)]);
-        let ephemeral_private_key: FE = ECScalar::from(&hash_private_key_message);
-        let ephemeral_public_key = base_point.scalar_mul(&ephemeral_private_key.get_element());
-        let (commitment, blind_factor) =
-            HashCommitment::create_commitment(&ephemeral_public_key.bytes_compressed_to_big_int());
-        EphemeralKey {
-            keypair: KeyPair {
-                public_key: ephemeral_public_key,
-                private_key: ephemeral_private_key,
-            },
-            commitment,
-            blind_factor,
+    // the handle of pk, None if pk is not in the set
+    pub fn handle(&self, pk: &GE) -> Option<SignerHandle> {
+        KeyAgg::sorted_position(&self.pks, pk).map(|index| SignerHandle {
+            set_id: self.id,
+            index,
+        })
+    }
+
+    // the sorted key list
+    pub fn pks(&self) -> &[GE] {
+        &self.pks
+    }
+
+    pub fn key_aggregation(&self, handle: &SignerHandle) -> Result<KeyAgg, MuSigError> {
+        self.check_handle(handle)?;
+        KeyAgg::key_aggregation_n(&self.pks, handle.index)
+    }
+
+    // EphemeralKey::sign with the coefficient of the member, which must hold keypair
+    pub fn sign<C: CommitmentScheme>(
+        &self,
+        handle: &SignerHandle,
+        r: &EphemeralKey<C>,
+        c: &BigInt,
+        keypair: &KeyPair,
+    ) -> Result<BigInt, MuSigError> {
+        let key_agg = self.key_aggregation(handle)?;
+        if self.pks[handle.index] != keypair.public_key {
+            return Err(MuSigError::HandleMismatch);
         }
+        Ok(EphemeralKey::sign(r, c, keypair, &key_agg.hash))
     }
 
-    pub fn test_com(r_to_test: &GE, blind_factor: &BigInt, comm: &BigInt) -> bool {
-        let computed_comm = &HashCommitment::create_commitment_with_user_defined_randomness(
-            &r_to_test.bytes_compressed_to_big_int(),
-            blind_factor,
-        );
-        computed_comm == comm
+    fn check_handle(&self, handle: &SignerHandle) -> Result<(), MuSigError> {
+        if handle.set_id != self.id {
+            return Err(MuSigError::HandleMismatch);
+        }
+        Ok(())
     }
+}
 
-    pub fn add_ephemeral_pub_keys(r1: &GE, r2: &GE) -> GE {
-        r1.add_point(&r2.get_element())
+impl SignerHandle {
+    // the position in the sorted key list of the set
+    pub fn index(&self) -> usize {
+        self.index
     }
+}
 
-    pub fn hash_0(r_hat: &GE, apk: &GE, message: &[u8], musig_bit: bool) -> BigInt {
-        if musig_bit {
-            HSha256::create_hash(&[
-                &BigInt::from(0),
-                &r_hat.x_coor().unwrap(),
-                &apk.bytes_compressed_to_big_int(),
-                &BigInt::from(message),
-            ])
+// Key aggregation for a key list that grows over time. The keys and their compressed
+// encodings are kept sorted between calls, party_index in finalize refers to this order.
+// Every coefficient hash commits to the whole key list, so adding a key invalidates the
+// coefficients and apk, which are recomputed once on the next finalize and then reused for
+// every party_index until the next add_key. With the musig_spec_v1 feature the list is
+// committed to by a single hash L, see key_list.
+#[derive(Debug, Clone, Default)]
+pub struct KeyAggBuilder {
+    pks: Vec<GE>,
+    encoded_pks: Vec<BigInt>,
+    cache: Option<(GE, Vec<BigInt>, bool)>,
+}
+
+impl KeyAggBuilder {
+    pub fn new() -> KeyAggBuilder {
+        KeyAggBuilder::default()
+    }
+
+    pub fn add_key(&mut self, pk: &GE) {
+        let encoded_pk = pk.bytes_compressed_to_big_int();
+        let index = match self.encoded_pks.binary_search(&encoded_pk) {
+            Ok(index) | Err(index) => index,
+        };
+        self.pks.insert(index, *pk);
+        self.encoded_pks.insert(index, encoded_pk);
+        self.cache = None;
+    }
+
+    pub fn len(&self) -> usize {
+        self.pks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pks.is_empty()
+    }
+
+    // the keys are kept sorted, so a repeated key sits next to its copy
+    pub fn has_duplicate_keys(&self) -> bool {
+        self.encoded_pks.windows(2).any(|pair| pair[0] == pair[1])
+    }
+
+    pub fn finalize(&mut self, party_index: usize) -> KeyAgg {
+        assert!(party_index < self.pks.len());
+        if self.cache.is_none() {
+            self.cache = Some(self.compute::<DefaultHash>());
+        }
+        let (apk, hash_vec, negated) = self.cache.as_ref().unwrap();
+        KeyAgg {
+            apk: *apk,
+            hash: hash_vec[party_index].clone(),
+            negated: *negated,
+            tweak: zero_tweak(),
+        }
+    }
+
+    // finalize with the coefficients hashed by H instead of DefaultHash. The result is not
+    // cached.
+    pub fn finalize_with_hash<H: Hash>(&self, party_index: usize) -> KeyAgg {
+        assert!(party_index < self.pks.len());
+        let (apk, mut hash_vec, negated) = self.compute::<H>();
+        KeyAgg {
+            apk,
+            hash: hash_vec.swap_remove(party_index),
+            negated,
+            tweak: zero_tweak(),
+        }
+    }
+
+    fn compute<H: Hash>(&self) -> (GE, Vec<BigInt>, bool) {
+        KeyAggBuilder::sum_weighted_keys(self.coefficients::<H>())
+    }
+
+    // the a_i and a_i·pk_i of all keys in key order, on the rayon thread pool with the
+    // parallel feature
+    fn coefficients<H: Hash>(&self) -> Vec<(BigInt, GE)> {
+        #[cfg(feature = "parallel")]
+        let weighted_keys = self.weighted_keys_parallel::<H>();
+        #[cfg(not(feature = "parallel"))]
+        let weighted_keys = self.weighted_keys::<H>();
+        weighted_keys
+    }
+
+    // the coefficient a_i = H("musig/agg", pk_i, pk_1, ..., pk_n) and a_i·pk_i, or with
+    // musig_spec_v1 a_i = H("musig/agg", L, pk_i)
+    fn weighted_key<H: Hash>(
+        &self,
+        tag: &BigInt,
+        key_list: &[BigInt],
+        pk: &GE,
+        encoded_pk: &BigInt,
+    ) -> (BigInt, GE) {
+        let mut vec = vec![tag];
+        if cfg!(feature = "musig_spec_v1") {
+            vec.extend(key_list);
+            vec.push(encoded_pk);
         } else {
-            HSha256::create_hash(&[
-                &r_hat.x_coor().unwrap(),
-                &apk.bytes_compressed_to_big_int(),
-                &BigInt::from(message),
-            ])
+            vec.push(encoded_pk);
+            vec.extend(key_list);
         }
+        let hash = H::create_hash(&vec);
+        let hash_t: FE = ECScalar::from(&hash);
+        let weighted_pk = pk.scalar_mul(&hash_t.get_element());
+        (hash, weighted_pk)
     }
 
-    pub fn sign(r: &EphemeralKey, c: &BigInt, x: &KeyPair, a: &BigInt) -> BigInt {
-        let c_fe: FE = ECScalar::from(c);
-        let a_fe: FE = ECScalar::from(a);
-        let s_fe = r.keypair.private_key.clone() + (c_fe * x.private_key.clone() * a_fe);
-        s_fe.to_big_int()
+    // what every coefficient hash commits to for the key list: the sorted keys, or with
+    // musig_spec_v1 only L = H("musig/keylist", pk_1, ..., pk_n), which is hashed once instead
+    // of once per key
+    fn key_list<H: Hash>(&self) -> Vec<BigInt> {
+        if cfg!(feature = "musig_spec_v1") {
+            let tag = BigInt::from(KEY_LIST_TAG.as_bytes());
+            let mut vec = vec![&tag];
+            vec.extend(&self.encoded_pks);
+            vec![H::create_hash(&vec)]
+        } else {
+            self.encoded_pks.clone()
+        }
     }
 
-    pub fn add_signature_parts(s1: BigInt, s2: &BigInt, r_tag: &GE) -> (BigInt, BigInt) {
-        if *s2 == BigInt::from(0) {
-            (r_tag.x_coor().unwrap(), s1)
+    // with the parallel feature only the tests use this, as the reference for the parallel path
+    #[cfg_attr(feature = "parallel", allow(dead_code))]
+    fn weighted_keys<H: Hash>(&self) -> Vec<(BigInt, GE)> {
+        let tag = domain_tag(KEY_AGG_TAG, 1);
+        let key_list = self.key_list::<H>();
+        self.pks
+            .iter()
+            .zip(&self.encoded_pks)
+            .map(|(pk, encoded_pk)| self.weighted_key::<H>(&tag, &key_list, pk, encoded_pk))
+            .collect()
+    }
+
+    // same as weighted_keys with the hashes and scalar multiplications spread over the rayon
+    // thread pool, the result keeps the order of the keys
+    #[cfg(feature = "parallel")]
+    fn weighted_keys_parallel<H: Hash>(&self) -> Vec<(BigInt, GE)> {
+        let tag = domain_tag(KEY_AGG_TAG, 1);
+        let key_list = self.key_list::<H>();
+        self.pks
+            .par_iter()
+            .zip(self.encoded_pks.par_iter())
+            .map(|(pk, encoded_pk)| self.weighted_key::<H>(&tag, &key_list, pk, encoded_pk))
+            .collect()
+    }
+
+    // folds the a_i·pk_i in key order, so the serial and parallel paths give the same apk
+    fn sum_weighted_keys(weighted_keys: Vec<(BigInt, GE)>) -> (GE, Vec<BigInt>, bool) {
+        let (hash_vec, mut apk_vec): (Vec<BigInt>, Vec<GE>) = weighted_keys.into_iter().unzip();
+        let pk1 = apk_vec.remove(0);
+        let sum = apk_vec
+            .iter()
+            .fold(pk1, |acc, pk| acc.add_point(&pk.get_element()));
+        if point_has_even_y(&sum) {
+            (sum, hash_vec, false)
         } else {
-            let s1_fe: FE = ECScalar::from(&s1);
-            let s2_fe: FE = ECScalar::from(&s2);
-            let s1_plus_s2 = s1_fe.add(&s2_fe.get_element());
-            (r_tag.x_coor().unwrap(), s1_plus_s2.to_big_int())
+            let negated_hash_vec = hash_vec
+                .iter()
+                .map(|hash| negate_scalar(&ECScalar::from(hash)).to_big_int())
+                .collect();
+            (negate_point(&sum), negated_hash_vec, true)
         }
     }
 }
 
-pub fn verify(
-    signature: &BigInt,
-    r_x: &BigInt,
-    apk: &GE,
-    message: &[u8],
-    musig_bit: bool,
-) -> Result<(), ProofError> {
-    let base_point: GE = ECPoint::generator();
+// The commitment to the ephemeral public key R sent in the first round. Every party has to
+// use the same scheme. HashCommitment, H(R, blind_factor), is the default, PedersenCommitment
+// commits to H(R) as m·G + blind_factor·H and is perfectly hiding but only computationally
+// binding.
+pub trait CommitmentScheme {
+    type Commitment: Clone + fmt::Debug + PartialEq + Serialize + DeserializeOwned;
 
-    let c = if musig_bit {
-        HSha256::create_hash(&[
-            &BigInt::from(0),
-            &r_x,
-            &apk.bytes_compressed_to_big_int(),
-            &BigInt::from(message),
-        ])
-    } else {
-        HSha256::create_hash(&[
-            r_x,
-            &apk.bytes_compressed_to_big_int(),
-            &BigInt::from(message),
-        ])
-    };
+    fn sample_blind_factor<R: RngCore + CryptoRng>(rng: &mut R) -> BigInt;
 
-    let signature_fe: FE = ECScalar::from(signature);
-    let sG = base_point.scalar_mul(&signature_fe.get_element());
-    let c: FE = ECScalar::from(&c);
-    let cY = apk.scalar_mul(&c.get_element());
-    let sG = sG.sub_point(&cY.get_element());
-    if sG.x_coor().unwrap().to_hex() == r_x.to_hex() {
-        Ok(())
-    } else {
-        Err(ProofError)
+    fn commit(r: &GE, blind_factor: &BigInt) -> Self::Commitment;
+
+    fn verify(r: &GE, blind_factor: &BigInt, commitment: &Self::Commitment) -> bool;
+}
+
+impl CommitmentScheme for HashCommitment {
+    type Commitment = BigInt;
+
+    fn sample_blind_factor<R: RngCore + CryptoRng>(rng: &mut R) -> BigInt {
+        let mut blind_bytes = [0u8; 32];
+        rng.fill_bytes(&mut blind_bytes);
+        BigInt::from(&blind_bytes[..])
+    }
+
+    fn commit(r: &GE, blind_factor: &BigInt) -> BigInt {
+        HashCommitment::create_commitment_with_user_defined_randomness(
+            &r.bytes_compressed_to_big_int(),
+            blind_factor,
+        )
+    }
+
+    fn verify(r: &GE, blind_factor: &BigInt, commitment: &BigInt) -> bool {
+        constant_time_eq(
+            &<HashCommitment as CommitmentScheme>::commit(r, blind_factor),
+            commitment,
+        )
     }
 }
 
-pub fn verify_partial(
-    signature: &FE,
-    r_x: &BigInt,
-    c: &FE,
-    a: &FE,
-    key_pub: &GE,
-) -> Result<(), ProofError> {
-    let g: GE = ECPoint::generator();
-    let sG = g * signature;
-    let cY = key_pub * a * c;
-    let sG = sG.sub_point(&cY.get_element());
-    if sG.x_coor().unwrap().to_hex() == *r_x.to_hex() {
-        Ok(())
-    } else {
-        Err(ProofError)
+impl CommitmentScheme for PedersenCommitment {
+    type Commitment = GE;
+
+    // the blinding scalar must be uniform in [1, q) for the commitment to hide R
+    fn sample_blind_factor<R: RngCore + CryptoRng>(rng: &mut R) -> BigInt {
+        random_scalar(rng).to_big_int()
     }
+
+    // R is hashed first, its 33 byte encoding is not a scalar
+    fn commit(r: &GE, blind_factor: &BigInt) -> GE {
+        let message = HSha256::create_hash(&[&r.bytes_compressed_to_big_int()]);
+        PedersenCommitment::create_commitment_with_user_defined_randomness(&message, blind_factor)
+    }
+
+    fn verify(r: &GE, blind_factor: &BigInt, commitment: &GE) -> bool {
+        <PedersenCommitment as CommitmentScheme>::commit(r, blind_factor) == *commitment
+    }
+}
+
+// INSECURE, no commitment at all: R is sent in the clear in the first round. A co-signer that
+// sees the other nonces before choosing its own can forge signatures with the attack of
+// Drijvers et al. (https://eprint.iacr.org/2018/417.pdf). Only for trusted test setups where
+// every party runs in one process, see EphemeralKey::create_no_commit.
+#[cfg(feature = "unsafe_no_commitment")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NoCommitment;
+
+#[cfg(feature = "unsafe_no_commitment")]
+impl CommitmentScheme for NoCommitment {
+    type Commitment = ();
+
+    fn sample_blind_factor<R: RngCore + CryptoRng>(_rng: &mut R) -> BigInt {
+        BigInt::from(0)
+    }
+
+    fn commit(_r: &GE, _blind_factor: &BigInt) {}
+
+    fn verify(_r: &GE, _blind_factor: &BigInt, _commitment: &()) -> bool {
+        true
+    }
+}
+
+// like KeyPair, a serialized EphemeralKey contains the secret nonce. The functions that
+// do not depend on the commitment scheme take an EphemeralKey<C> of any scheme.
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct EphemeralKey<C: CommitmentScheme = HashCommitment> {
+    pub keypair: KeyPair,
+    pub commitment: C::Commitment,
+    pub blind_factor: BigInt,
+}
+
+impl<C: CommitmentScheme> EphemeralKey<C> {
+    // create with the commitment scheme C, e.g.
+    // EphemeralKey::<PedersenCommitment>::create_with_scheme()
+    pub fn create_with_scheme() -> EphemeralKey<C> {
+        with_default_rng(EphemeralKey::create_with_scheme_rng)
+    }
+
+    // both the nonce and the blind factor of the commitment are drawn from rng
+    pub fn create_with_scheme_rng<R: RngCore + CryptoRng>(rng: &mut R) -> EphemeralKey<C> {
+        let keypair = KeyPair::create_with_rng(rng);
+        let blind_factor = C::sample_blind_factor(rng);
+        let commitment = C::commit(&keypair.public_key, &blind_factor);
+        EphemeralKey {
+            keypair,
+            commitment,
+            blind_factor,
+        }
+    }
+
+    // replaces the nonce k by -k and R by -R
+    pub fn negate(&mut self) {
+        self.keypair.private_key =
+            PrivateKey::from_scalar(negate_scalar(&self.keypair.private_key.to_scalar())).unwrap();
+        self.keypair.public_key = negate_point(&self.keypair.public_key);
+    }
+
+    // s = r + c·a·x with c and a taken from ctx. The nonce and the private key only enter
+    // FE arithmetic, never BigInt::mod_mul/mod_add, and the result is the same as the BigInt
+    // computation mod q. FE is only as constant time as curv makes it, see the README.
+    pub fn sign_in_context(&self, ctx: &SigningContext, keypair: &KeyPair) -> BigInt {
+        let c_fe: FE = ECScalar::from(&ctx.challenge);
+        let a_fe: FE = ECScalar::from(&ctx.coefficient);
+        let s_fe =
+            self.keypair.private_key.to_scalar() + (c_fe * keypair.private_key.to_scalar() * a_fe);
+        s_fe.to_big_int()
+    }
+
+    // sign_in_context bundled with the nonce point R_i, after negate if R_hat was negated, and
+    // the position index of keypair in the sorted key list, see KeyAgg::sorted_position. This
+    // is all a coordinator needs to check the share and aggregate it.
+    pub fn sign_round2(
+        &self,
+        ctx: &SigningContext,
+        keypair: &KeyPair,
+        index: usize,
+    ) -> PartialSignature {
+        PartialSignature {
+            r_i: self.keypair.public_key,
+            s_i: self.sign_in_context(ctx, keypair),
+            index,
+        }
+    }
+}
+
+#[cfg(feature = "unsafe_no_commitment")]
+impl EphemeralKey<NoCommitment> {
+    // INSECURE against the Drijvers attack, see NoCommitment. A nonce for trusted test
+    // setups that exchange R directly and skip the commitment round.
+    pub fn create_no_commit() -> EphemeralKey<NoCommitment> {
+        EphemeralKey::create_with_scheme()
+    }
+}
+
+impl EphemeralKey {
+    pub fn create() -> EphemeralKey {
+        with_default_rng(EphemeralKey::create_with_rng)
+    }
+
+    pub fn create_with_rng<R: RngCore + CryptoRng>(rng: &mut R) -> EphemeralKey {
+        EphemeralKey::create_with_scheme_rng(rng)
+    }
+
+    // n nonces for n sessions, drawn from one rng that is set up once instead of once per
+    // create. Every nonce and blind factor is still sampled on its own.
+    pub fn create_batch(n: usize) -> Vec<EphemeralKey> {
+        with_default_rng(|rng| (0..n).map(|_| EphemeralKey::create_with_rng(rng)).collect())
+    }
+
+    // nonce derived from H(x, m) only: it is not bound to the public key and takes no
+    // auxiliary randomness, see create_deterministic for the BIP340 style derivation
+    pub fn create_from_private_key(x1: &KeyPair, message: &[u8]) -> EphemeralKey {
+        let hash_private_key_message =
+            HSha256::create_hash(&[&x1.expose_secret(), &message_to_field_input(message)]);
+        EphemeralKey::from_ephemeral_private_key(ECScalar::from(&hash_private_key_message))
+    }
+
+    // BIP340 style nonce k = H(t, apk, m) where t = x xor SHA256(aux_rand), or t = x if no
+    // auxiliary randomness is given. apk is the key the signature is made for, the aggregated
+    // key in a multi party session or the own public key for a single signer, so the nonce
+    // changes with the set of co-signers. aux_rand is hashed as raw bytes, leading zero bytes
+    // included. The same inputs always yield the same nonce: never sign two messages in
+    // sessions whose other nonces differ with it.
+    pub fn create_deterministic(
+        keypair: &KeyPair,
+        apk: &GE,
+        message: &[u8],
+        aux_rand: Option<&[u8]>,
+    ) -> EphemeralKey {
+        let private_key_bn = keypair.expose_secret();
+        let t = match aux_rand {
+            Some(aux_rand) => {
+                let aux_hash = Sha256::digest(aux_rand);
+                let masked_key = to_fixed_bytes(&private_key_bn, 32)
+                    .iter()
+                    .zip(aux_hash.iter())
+                    .map(|(x_byte, aux_byte)| x_byte ^ aux_byte)
+                    .collect::<Vec<u8>>();
+                BigInt::from(&masked_key[..])
+            }
+            None => private_key_bn,
+        };
+        let nonce = HSha256::create_hash(&[
+            &t,
+            &apk.bytes_compressed_to_big_int(),
+            &message_to_field_input(message),
+        ]);
+        EphemeralKey::from_ephemeral_private_key(ECScalar::from(&nonce))
+    }
+
+    // create_deterministic for a signature over a digest, see hash_0_prehashed
+    pub fn create_deterministic_prehashed(
+        keypair: &KeyPair,
+        apk: &GE,
+        digest: &[u8; 32],
+        aux_rand: Option<&[u8]>,
+    ) -> EphemeralKey {
+        EphemeralKey::create_deterministic(keypair, apk, digest, aux_rand)
+    }
+
+    // a nonce held by an external signer, e.g. a secure element, that only reveals R = k·G.
+    // nonce_provider gets the challenge c and returns s = k + c·a·x mod q, the same share as
+    // sign, so k never enters this crate. If aggregate_ephemeral_pub_keys negated R_hat the
+    // provider has to sign with -k, as EphemeralKey::negate does for an in process nonce.
+    pub fn from_external_nonce<F: FnOnce(&BigInt) -> BigInt>(
+        r_pub: GE,
+        nonce_provider: F,
+    ) -> ExternalEphemeralKey<F> {
+        let (commitment, blind_factor) =
+            HashCommitment::create_commitment(&r_pub.bytes_compressed_to_big_int());
+        ExternalEphemeralKey {
+            r_pub,
+            commitment,
+            blind_factor,
+            nonce_provider,
+        }
+    }
+
+    fn from_ephemeral_private_key(ephemeral_private_key: FE) -> EphemeralKey {
+        // a hash output that is zero mod q is not expected to ever be found
+        let keypair = KeyPair::from_secret_scalar(ephemeral_private_key).expect("zero nonce");
+        let (commitment, blind_factor) =
+            HashCommitment::create_commitment(&keypair.public_key.bytes_compressed_to_big_int());
+        EphemeralKey {
+            keypair,
+            commitment,
+            blind_factor,
+        }
+    }
+
+    pub fn test_com(r_to_test: &GE, blind_factor: &BigInt, comm: &BigInt) -> bool {
+        EphemeralKey::verify_commitment(r_to_test, blind_factor, comm).is_ok()
+    }
+
+    pub fn verify_commitment(
+        r: &GE,
+        blind_factor: &BigInt,
+        comm: &BigInt,
+    ) -> Result<(), CommitmentError> {
+        if <HashCommitment as CommitmentScheme>::verify(r, blind_factor, comm) {
+            Ok(())
+        } else {
+            Err(CommitmentError {
+                party: None,
+                commitment: comm.clone(),
+            })
+        }
+    }
+
+    // checks the opening reveals[i] against comms[i] for every party, the error gives the
+    // index of the first opening that does not match
+    pub fn verify_commitments(
+        reveals: &[(GE, BigInt)],
+        comms: &[BigInt],
+    ) -> Result<(), MuSigError> {
+        if reveals.len() != comms.len() {
+            return Err(MuSigError::IndexOutOfRange);
+        }
+        match reveals
+            .iter()
+            .zip(comms)
+            .position(|((r, blind_factor), comm)| !EphemeralKey::test_com(r, blind_factor, comm))
+        {
+            Some(index) => Err(MuSigError::CommitmentMismatch { index: Some(index) }),
+            None => Ok(()),
+        }
+    }
+
+    pub fn add_ephemeral_pub_keys(r1: &GE, r2: &GE) -> GE {
+        r1.add_point(&r2.get_element())
+    }
+
+    // partial signature of the designated party under a key tweaked with KeyAgg::add_tweak:
+    // s = k + c·x·a + c·t, with t the tweak of the tweaked KeyAgg
+    pub fn sign_with_tweak<C: CommitmentScheme>(
+        r: &EphemeralKey<C>,
+        c: &BigInt,
+        x: &KeyPair,
+        a: &BigInt,
+        t: &BigInt,
+    ) -> BigInt {
+        let c_fe: FE = ECScalar::from(c);
+        let t_fe: FE = ECScalar::from(t);
+        let s_fe: FE = ECScalar::from(&EphemeralKey::sign(r, c, x, a));
+        (s_fe + c_fe * t_fe).to_big_int()
+    }
+
+    // R_hat = sum of the ephemeral public keys of all parties, negated to even y. The flag
+    // tells whether R_hat was negated, in which case every party has to call negate on its
+    // ephemeral key before signing. secp256k1 has cofactor 1, so the only point outside the
+    // prime order group is the point at infinity, and a sum reaching it is rejected.
+    pub fn aggregate_ephemeral_pub_keys(rs: &[GE]) -> Result<(GE, bool), MuSigError> {
+        let (head, tail) = match rs.split_first() {
+            Some(split) => split,
+            None => return Err(MuSigError::EmptyKeySet),
+        };
+        let mut r_hat = *head;
+        for r in tail {
+            if *r == negate_point(&r_hat) {
+                return Err(MuSigError::InvalidPoint);
+            }
+            r_hat = EphemeralKey::add_ephemeral_pub_keys(&r_hat, r);
+        }
+        if point_has_even_y(&r_hat) {
+            Ok((r_hat, false))
+        } else {
+            Ok((negate_point(&r_hat), true))
+        }
+    }
+
+    // mode is a ChallengeMode or, for compatibility, the musig bit. The message is anything
+    // that gives bytes: a slice, a byte array, a Vec<u8> or a String hash the same.
+    pub fn hash_0<T: AsRef<[u8]>, M: Into<ChallengeMode>>(
+        r_hat: &GE,
+        apk: &GE,
+        message: T,
+        mode: M,
+    ) -> BigInt {
+        compute_challenge(&r_hat.x_coor().unwrap(), apk, message.as_ref(), mode.into())
+    }
+
+    // hash_0 computed with H instead of DefaultHash
+    pub fn hash_0_with_hash<H: Hash, M: Into<ChallengeMode>>(
+        r_hat: &GE,
+        apk: &GE,
+        message: &[u8],
+        mode: M,
+    ) -> BigInt {
+        compute_challenge_with_hash::<H>(&r_hat.x_coor().unwrap(), apk, message, mode.into())
+    }
+
+    // challenge over a 32 byte digest, e.g. a transaction sighash or the output of
+    // MessageHasher, used as the message bytes. The digest is not hashed again, so the
+    // signature only verifies with verify_prehashed on the same digest and never with verify
+    // on the original message.
+    pub fn hash_0_prehashed<M: Into<ChallengeMode>>(
+        r_hat: &GE,
+        apk: &GE,
+        digest: &[u8; 32],
+        mode: M,
+    ) -> BigInt {
+        EphemeralKey::hash_0(r_hat, apk, digest, mode)
+    }
+
+    pub fn sign<C: CommitmentScheme>(
+        r: &EphemeralKey<C>,
+        c: &BigInt,
+        x: &KeyPair,
+        a: &BigInt,
+    ) -> BigInt {
+        let ctx = SigningContext {
+            challenge: c.clone(),
+            coefficient: a.clone(),
+        };
+        r.sign_in_context(&ctx, x)
+    }
+
+    // s = sum of the partial signatures mod q
+    pub fn aggregate_signature_parts(
+        shares: &[BigInt],
+        r_tag: &GE,
+    ) -> Result<Signature, MuSigError> {
+        if shares.is_empty() {
+            return Err(MuSigError::EmptyKeySet);
+        }
+        if !shares.iter().all(is_reduced_scalar) {
+            return Err(MuSigError::InvalidScalar);
+        }
+        let s = shares.iter().fold(FE::zero(), |acc, s_i| {
+            let s_i: FE = ECScalar::from(s_i);
+            acc + s_i
+        });
+        Ok(Signature {
+            s: s.to_big_int(),
+            r_x: r_tag.x_coor().unwrap(),
+        })
+    }
+
+    // s = s1 + s2 mod q, both parts must be reduced mod q
+    pub fn add_signature_parts(
+        s1: BigInt,
+        s2: &BigInt,
+        r_tag: &GE,
+    ) -> Result<Signature, MuSigError> {
+        if !is_reduced_scalar(&s1) || !is_reduced_scalar(s2) {
+            return Err(MuSigError::InvalidScalar);
+        }
+        if *s2 == BigInt::from(0) {
+            Ok(Signature {
+                s: s1,
+                r_x: r_tag.x_coor().unwrap(),
+            })
+        } else {
+            let s1_fe: FE = ECScalar::from(&s1);
+            let s2_fe: FE = ECScalar::from(&s2);
+            let s1_plus_s2 = s1_fe.add(&s2_fe.get_element());
+            Ok(Signature {
+                s: s1_plus_s2.to_big_int(),
+                r_x: r_tag.x_coor().unwrap(),
+            })
+        }
+    }
+
+    // add_signature_parts keeping the whole nonce point R instead of only r_x
+    pub fn add_signature_parts_full(
+        s1: BigInt,
+        s2: &BigInt,
+        r_tag: &GE,
+    ) -> Result<(GE, BigInt), MuSigError> {
+        let signature = EphemeralKey::add_signature_parts(s1, s2, r_tag)?;
+        Ok((*r_tag, signature.s))
+    }
+}
+
+// the blind factor hides the nonce until the commitment is opened, so it is redacted as well
+impl<C: CommitmentScheme> fmt::Debug for EphemeralKey<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EphemeralKey")
+            .field("keypair", &self.keypair)
+            .field("commitment", &self.commitment)
+            .field("blind_factor", &"REDACTED")
+            .finish()
+    }
+}
+
+// Safety net against signing twice with one nonce, which reveals the private key. Records
+// the x coordinate of every nonce point R used with a key: R and -R are the same nonce up to
+// sign, and partial signatures under both reveal the key as well. The tracker only knows the
+// nonces it has seen, so it has to live as long as the key.
+#[derive(Debug, Clone, Default)]
+pub struct NonceTracker {
+    used: HashSet<(Vec<u8>, Vec<u8>)>,
+}
+
+impl NonceTracker {
+    pub fn new() -> NonceTracker {
+        NonceTracker::default()
+    }
+
+    // records r for pk, NonceReuse if it was recorded before
+    pub fn check_and_record(&mut self, pk: &GE, r: &GE) -> Result<(), MuSigError> {
+        let r_x = to_fixed_bytes(&r.x_coor().unwrap(), 32);
+        if self.used.insert((compressed_bytes(pk), r_x)) {
+            Ok(())
+        } else {
+            Err(MuSigError::NonceReuse)
+        }
+    }
+
+    // EphemeralKey::sign after check_and_record
+    pub fn sign<C: CommitmentScheme>(
+        &mut self,
+        r: &EphemeralKey<C>,
+        c: &BigInt,
+        x: &KeyPair,
+        a: &BigInt,
+    ) -> Result<BigInt, MuSigError> {
+        self.check_and_record(&x.public_key, &r.keypair.public_key)?;
+        Ok(EphemeralKey::sign(r, c, x, a))
+    }
+}
+
+// the public half of a nonce held outside the crate, see EphemeralKey::from_external_nonce.
+// r_pub, commitment and blind_factor take the place of the EphemeralKey fields in the
+// commitment rounds.
+pub struct ExternalEphemeralKey<F: FnOnce(&BigInt) -> BigInt> {
+    pub r_pub: GE,
+    pub commitment: BigInt,
+    pub blind_factor: BigInt,
+    nonce_provider: F,
+}
+
+impl<F: FnOnce(&BigInt) -> BigInt> ExternalEphemeralKey<F> {
+    // the partial signature for the challenge c, computed by the provider. Consumes the key,
+    // so the provider is asked for at most one share per nonce.
+    pub fn sign(self, c: &BigInt) -> Result<BigInt, MuSigError> {
+        let s = (self.nonce_provider)(c);
+        if !is_reduced_scalar(&s) {
+            return Err(MuSigError::InvalidScalar);
+        }
+        Ok(s)
+    }
+}
+
+// the session values a partial signature is computed over: the challenge c and the signer's
+// aggregation coefficient a. The aggregated key only enters s through c, so it is not kept.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SigningContext {
+    pub challenge: BigInt,
+    pub coefficient: BigInt,
+}
+
+impl SigningContext {
+    // c = hash_0(R', apk, message) and the coefficient of the signer in key_agg
+    pub fn new<M: Into<ChallengeMode>>(
+        r_tag: &GE,
+        key_agg: &KeyAgg,
+        message: &[u8],
+        mode: M,
+    ) -> SigningContext {
+        SigningContext {
+            challenge: EphemeralKey::hash_0(r_tag, &key_agg.apk, message, mode),
+            coefficient: key_agg.hash.clone(),
+        }
+    }
+}
+
+// the output of EphemeralKey::sign_round2
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PartialSignature {
+    pub r_i: GE,
+    pub s_i: BigInt,
+    pub index: usize,
+}
+
+impl PartialSignature {
+    // verify_partial_signature for the share, pk_i and a_i are the key and coefficient at index
+    pub fn verify(&self, pk_i: &GE, a_i: &BigInt, c: &BigInt) -> Result<(), MuSigError> {
+        verify_partial_signature(&self.s_i, &self.r_i, pk_i, a_i, c)
+    }
+
+    // R' = sum of the r_i and s = sum of the s_i, one share per signer
+    pub fn aggregate(parts: &[PartialSignature]) -> Result<Signature, MuSigError> {
+        let rs: Vec<GE> = parts.iter().map(|part| part.r_i).collect();
+        let (r_hat, _) = EphemeralKey::aggregate_ephemeral_pub_keys(&rs)?;
+        let shares: Vec<BigInt> = parts.iter().map(|part| part.s_i.clone()).collect();
+        EphemeralKey::aggregate_signature_parts(&shares, &r_hat)
+    }
+}
+
+// a finished signature: the x coordinate of the aggregated nonce R' and the aggregated s
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature {
+    pub s: BigInt,
+    pub r_x: BigInt,
+}
+
+impl Signature {
+    // 64 byte encoding: 32 byte big endian r_x followed by the 32 byte big endian s
+    pub fn to_bytes(&self) -> [u8; 64] {
+        let mut bytes = [0u8; 64];
+        bytes[..32].copy_from_slice(&to_fixed_bytes(&self.r_x, 32));
+        bytes[32..].copy_from_slice(&to_fixed_bytes(&self.s, 32));
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8; 64]) -> Result<Signature, MuSigError> {
+        let r_x = BigInt::from(&bytes[..32]);
+        let s = BigInt::from(&bytes[32..]);
+        if s >= curve_order() {
+            return Err(MuSigError::InvalidScalar);
+        }
+        Ok(Signature { s, r_x })
+    }
+
+    // ASN.1 DER SEQUENCE { INTEGER r_x, INTEGER s }, for systems that expect Schnorr
+    // signatures in the ECDSA style encoding. At most 72 bytes, so the lengths are one byte.
+    pub fn to_der(&self) -> Vec<u8> {
+        let mut content = der_integer(&self.r_x);
+        content.extend(der_integer(&self.s));
+        let mut bytes = vec![0x30, content.len() as u8];
+        bytes.extend(content);
+        bytes
+    }
+
+    // parses the encoding of to_der. Only DER is accepted: short form lengths, integers without
+    // extra leading bytes, no negative values and nothing after the sequence.
+    pub fn from_der(bytes: &[u8]) -> Result<Signature, MuSigError> {
+        if bytes.len() < 2 || bytes[0] != 0x30 || bytes[1] as usize != bytes.len() - 2 {
+            return Err(MuSigError::InvalidEncoding);
+        }
+        let (r_x, rest) = parse_der_integer(&bytes[2..])?;
+        let (s, rest) = parse_der_integer(rest)?;
+        if !rest.is_empty() {
+            return Err(MuSigError::InvalidEncoding);
+        }
+        if s >= curve_order() {
+            return Err(MuSigError::InvalidScalar);
+        }
+        Ok(Signature { s, r_x })
+    }
+
+    // cheap pre-check before verify: true iff bytes has 64 bytes and from_bytes accepts it.
+    // No curve operations are done, so a true result says nothing about validity.
+    pub fn is_valid_encoding(bytes: &[u8]) -> bool {
+        bytes.len() == 64 && BigInt::from(&bytes[32..]) < curve_order()
+    }
+
+    pub fn verify<T: AsRef<[u8]>, M: Into<ChallengeMode>>(
+        &self,
+        apk: &GE,
+        message: T,
+        mode: M,
+    ) -> Result<(), MuSigError> {
+        verify_sig(&self.s, &self.r_x, apk, message.as_ref(), mode.into())
+    }
+
+    // verify for a signature whose key aggregation and challenge were hashed with H
+    pub fn verify_with_hash<H: Hash, M: Into<ChallengeMode>>(
+        &self,
+        apk: &GE,
+        message: &[u8],
+        mode: M,
+    ) -> Result<(), MuSigError> {
+        let base_point: GE = ECPoint::generator();
+        verify_with::<H, _>(&self.s, &self.r_x, apk, message, mode.into(), |s| {
+            base_point.scalar_mul(&s.get_element())
+        })
+        .map(|_| ())
+        .map_err(MuSigError::from)
+    }
+
+    // verify returning the challenge hash c = hash_0(R', apk, message) it checked the
+    // signature against, e.g. for an audit log
+    pub fn verify_with_digest<M: Into<ChallengeMode>>(
+        &self,
+        apk: &GE,
+        message: &[u8],
+        mode: M,
+    ) -> Result<BigInt, MuSigError> {
+        let base_point: GE = ECPoint::generator();
+        verify_with::<DefaultHash, _>(&self.s, &self.r_x, apk, message, mode.into(), |s| {
+            base_point.scalar_mul(&s.get_element())
+        })
+        .map_err(MuSigError::from)
+    }
+
+    // s <= (q - 1) / 2
+    pub fn is_low_s(&self) -> bool {
+        self.s <= (curve_order() - BigInt::from(1)) >> 1
+    }
+
+    // verify that also rejects s in the upper half of [0, q), for uses that need a single
+    // accepted encoding. Unlike ECDSA, q - s is not a valid signature on the same r_x, so there
+    // is no normalize: a signer that gets a high s has to sign again with a fresh nonce.
+    pub fn verify_low_s<M: Into<ChallengeMode>>(
+        &self,
+        apk: &GE,
+        message: &[u8],
+        mode: M,
+    ) -> Result<(), MuSigError> {
+        if !self.is_low_s() {
+            return Err(MuSigError::InvalidScalar);
+        }
+        self.verify(apk, message, mode)
+    }
+
+    // like verify, but lifts r_x to the point R with even y and checks sG == R + cP as points,
+    // so a signature whose nonce has odd y is rejected, as in BIP340
+    pub fn verify_strict<M: Into<ChallengeMode>>(
+        &self,
+        apk: &GE,
+        message: &[u8],
+        mode: M,
+    ) -> Result<(), MuSigError> {
+        let r = match lift_x_with_parity(&self.r_x, false) {
+            Some(r) => r,
+            None => return Err(MuSigError::InvalidPoint),
+        };
+        if apk.x_coor().is_none() {
+            return Err(MuSigError::InvalidPoint);
+        }
+        if self.s == BigInt::from(0) || self.s >= curve_order() {
+            return Err(MuSigError::InvalidScalar);
+        }
+        let c: FE = ECScalar::from(&compute_challenge(&self.r_x, apk, message, mode.into()));
+        let c_apk = *apk * c;
+        // R + cP is the point at infinity, which sG never is
+        if c_apk == negate_point(&r) {
+            return Err(MuSigError::InvalidSignature);
+        }
+        let s: FE = ECScalar::from(&self.s);
+        let base_point: GE = ECPoint::generator();
+        if base_point * s == r + c_apk {
+            Ok(())
+        } else {
+            Err(MuSigError::InvalidSignature)
+        }
+    }
+
+    // verifies a signature made with EphemeralKey::hash_0_prehashed
+    pub fn verify_prehashed<M: Into<ChallengeMode>>(
+        &self,
+        apk: &GE,
+        digest: &[u8; 32],
+        mode: M,
+    ) -> Result<(), MuSigError> {
+        self.verify(apk, digest, mode)
+    }
+}
+
+// SHA256 of a message fed in chunks. In the prehashed mode the 32 byte digest takes the place
+// of the message in the challenge, so large messages never have to be held in memory.
+#[derive(Clone, Default)]
+pub struct MessageHasher {
+    hasher: Sha256,
+}
+
+impl MessageHasher {
+    pub fn new() -> MessageHasher {
+        MessageHasher::default()
+    }
+
+    pub fn update(&mut self, chunk: &[u8]) {
+        self.hasher.input(chunk);
+    }
+
+    pub fn finalize(self) -> [u8; 32] {
+        let mut digest = [0u8; 32];
+        digest.copy_from_slice(&self.hasher.result());
+        digest
+    }
+}
+
+// Accepts iff every (signature, apk, message, musig_bit) entry verifies. The entries are
+// first checked at once with random a_i by one multi scalar multiplication:
+//   sum a_i·R_i + sum (a_i·c_i)·P_i - (sum a_i·s_i)·G == O
+// over the 2n + 1 terms, with the terms of a key that signs several entries merged. A
+// signature only carries the x coordinate of R, so R_i is lifted with even y. Signatures with
+// an odd y R verify with Signature::verify but not in the combined check; the signatures of
+// aggregate_ephemeral_pub_keys and bip340 always have an even y R, those of sign_single and
+// EphemeralKey::sign with a nonce that was not negated not always. Whenever the combined check
+// fails, every entry is verified on its own, so the decision and the error are always those of
+// Signature::verify; only the fast path is lost. The error is a MuSigError rather than the
+// ProofError of the deprecated verify, as it carries the reason the first failing entry was
+// rejected.
+pub fn batch_verify(sigs: &[(Signature, GE, Vec<u8>, bool)]) -> Result<(), MuSigError> {
+    if batch_equation_holds(sigs) {
+        return Ok(());
+    }
+    sigs.iter()
+        .map(|(signature, apk, message, musig_bit)| signature.verify(apk, message, *musig_bit))
+        .collect()
+}
+
+// the combined check of batch_verify, false for an empty batch and for any entry that
+// Signature::verify would reject before the curve arithmetic
+fn batch_equation_holds(sigs: &[(Signature, GE, Vec<u8>, bool)]) -> bool {
+    if sigs.is_empty() {
+        return false;
+    }
+    let q = curve_order();
+    let coefficients: Vec<BigInt> = with_default_rng(|rng| {
+        sigs.iter()
+            .map(|_| random_scalar(rng).to_big_int())
+            .collect()
+    });
+    let mut s_sum = BigInt::from(0);
+    let mut terms: Vec<(BigInt, GE)> = Vec::with_capacity(2 * sigs.len() + 1);
+    let mut key_positions: HashMap<Vec<u8>, usize> = HashMap::new();
+    for ((signature, apk, message, musig_bit), a) in sigs.iter().zip(coefficients) {
+        if signature.s == BigInt::from(0) || signature.s >= q || apk.x_coor().is_none() {
+            return false;
+        }
+        let r = match lift_x_with_parity(&signature.r_x, false) {
+            Some(r) => r,
+            None => return false,
+        };
+        let c = compute_challenge(&signature.r_x, apk, message, (*musig_bit).into());
+        s_sum = BigInt::mod_add(&s_sum, &BigInt::mod_mul(&signature.s, &a, &q), &q);
+        let key_coefficient = BigInt::mod_mul(&c, &a, &q);
+        terms.push((a, r));
+        let position = *key_positions
+            .entry(compressed_bytes(apk))
+            .or_insert_with(|| {
+                terms.push((BigInt::from(0), *apk));
+                terms.len() - 1
+            });
+        terms[position].0 = BigInt::mod_add(&terms[position].0, &key_coefficient, &q);
+    }
+    let base_point: GE = ECPoint::generator();
+    terms.push((BigInt::mod_sub(&BigInt::from(0), &s_sum, &q), base_point));
+    multi_scalar_mul(&terms).is_none()
+}
+
+// verifies a MuSig signature for the key list pks, in any order, without a separate key
+// aggregation step
+pub fn verify_multisig(sig: &Signature, pks: &[GE], message: &[u8]) -> Result<(), MuSigError> {
+    sig.verify(&KeyAgg::aggregate_only(pks)?, message, ChallengeMode::Musig)
+}
+
+// verify for an aggregated key given as its 32 byte x coordinate, e.g. taken from a BIP340
+// context. apk always has even y, so the key is lifted to the point with even y; an x that is
+// not on the curve gives InvalidPoint. The challenge is the Musig one, bip340::verify checks
+// signatures with the BIP340 challenge instead.
+pub fn verify_xonly(sig: &Signature, apk_x: &[u8; 32], message: &[u8]) -> Result<(), MuSigError> {
+    let apk = match lift_x_with_parity(&BigInt::from(&apk_x[..]), false) {
+        Some(apk) => apk,
+        None => return Err(MuSigError::InvalidPoint),
+    };
+    sig.verify(&apk, message, ChallengeMode::Musig)
+}
+
+// proof of possession of the private key of a public key, see KeyPair::prove_possession
+#[derive(Debug, Clone, PartialEq)]
+pub struct PopProof {
+    pub signature: Signature,
+}
+
+pub fn verify_possession(pk: &GE, proof: &PopProof) -> Result<(), MuSigError> {
+    proof
+        .signature
+        .verify(pk, &pop_message(pk), ChallengeMode::Plain)
+}
+
+// "musig/pop" followed by the 33 byte compressed key, so a proof is never a signature over
+// a message the key would sign otherwise
+fn pop_message(pk: &GE) -> Vec<u8> {
+    let mut message = POP_TAG.as_bytes().to_vec();
+    message.extend_from_slice(&compressed_bytes(pk));
+    message
+}
+
+// Verifies signatures with a precomputed table of multiples of G: table[i][j - 1] = j·16^i·G
+// for the 64 four bit windows of a scalar, so sG takes at most 63 point additions and no
+// doublings. Building the table costs about a thousand additions, it pays off for a verifier
+// that checks many signatures. The results are the same as Signature::verify.
+#[derive(Debug, Clone)]
+pub struct Verifier {
+    table: Vec<Vec<GE>>,
+}
+
+impl Verifier {
+    pub fn new() -> Verifier {
+        Verifier {
+            table: window_table(&ECPoint::generator()),
+        }
+    }
+
+    pub fn verify<T: AsRef<[u8]>, M: Into<ChallengeMode>>(
+        &self,
+        signature: &Signature,
+        apk: &GE,
+        message: T,
+        mode: M,
+    ) -> Result<(), MuSigError> {
+        verify_with::<DefaultHash, _>(
+            &signature.s,
+            &signature.r_x,
+            apk,
+            message.as_ref(),
+            mode.into(),
+            |s| self.base_mul(s),
+        )
+        .map(|_| ())
+        .map_err(MuSigError::from)
+    }
+
+    pub fn verify_prehashed<M: Into<ChallengeMode>>(
+        &self,
+        signature: &Signature,
+        apk: &GE,
+        digest: &[u8; 32],
+        mode: M,
+    ) -> Result<(), MuSigError> {
+        self.verify(signature, apk, digest, mode)
+    }
+
+    fn base_mul(&self, s: &FE) -> GE {
+        window_mul(&self.table, s)
+    }
+}
+
+impl Default for Verifier {
+    fn default() -> Verifier {
+        Verifier::new()
+    }
+}
+
+// A Verifier for many signatures under one aggregated key. Besides the table of G it keeps
+// the same table of multiples of apk, so c·apk also takes at most 63 additions. The results
+// are the same as Signature::verify with apk.
+#[derive(Debug, Clone)]
+pub struct CachedVerifier {
+    apk: GE,
+    verifier: Verifier,
+    apk_table: Vec<Vec<GE>>,
+}
+
+impl CachedVerifier {
+    pub fn new(apk: GE) -> CachedVerifier {
+        CachedVerifier {
+            apk,
+            verifier: Verifier::new(),
+            apk_table: window_table(&apk),
+        }
+    }
+
+    pub fn apk(&self) -> &GE {
+        &self.apk
+    }
+
+    pub fn verify<T: AsRef<[u8]>, M: Into<ChallengeMode>>(
+        &self,
+        signature: &Signature,
+        message: T,
+        mode: M,
+    ) -> Result<(), MuSigError> {
+        verify_with_muls::<DefaultHash, _, _>(
+            &signature.s,
+            &signature.r_x,
+            &self.apk,
+            message.as_ref(),
+            mode.into(),
+            |s| self.verifier.base_mul(s),
+            |c| window_mul(&self.apk_table, c),
+        )
+        .map(|_| ())
+        .map_err(MuSigError::from)
+    }
+}
+
+// table[i][j - 1] = j·16^i·P for the 64 four bit windows of a scalar, see Verifier
+fn window_table(point: &GE) -> Vec<Vec<GE>> {
+    let mut table = Vec::with_capacity(64);
+    let mut window_base = *point;
+    for _ in 0..64 {
+        let mut multiples = Vec::with_capacity(15);
+        multiples.push(window_base);
+        for j in 1..15 {
+            let next = multiples[j - 1].add_point(&window_base.get_element());
+            multiples.push(next);
+        }
+        // 16·(16^i·P) = 15·(16^i·P) + 16^i·P
+        window_base = multiples[14].add_point(&window_base.get_element());
+        table.push(multiples);
+    }
+    table
+}
+
+// s·P for s in [1, q) from the window_table of P. The partial sums are t·P with
+// 0 < t <= s < q, so no addition hits the point at infinity.
+fn window_mul(table: &[Vec<GE>], s: &FE) -> GE {
+    let bytes = to_fixed_bytes(&s.to_big_int(), 32);
+    let mut sum: Option<GE> = None;
+    for (i, byte) in bytes.iter().rev().enumerate() {
+        for (k, nibble) in [byte & 0x0f, byte >> 4].iter().enumerate() {
+            if *nibble == 0 {
+                continue;
+            }
+            let term = table[2 * i + k][*nibble as usize - 1];
+            sum = Some(match sum {
+                Some(acc) => acc.add_point(&term.get_element()),
+                None => term,
+            });
+        }
+    }
+    sum.expect("s is not zero")
+}
+
+// sum of k_j·P_j for scalars k_j in [0, q) by Pippenger's bucket method: for every window of
+// c bits, from the top, each point is added to the bucket of its digit and the buckets are
+// summed with the weights 1 to 2^c - 1 by running sums. That is about 256/c·(n + 2^(c + 1))
+// additions for n terms instead of the about 256 of one scalar multiplication per term. None
+// is the point at infinity.
+fn multi_scalar_mul(terms: &[(BigInt, GE)]) -> Option<GE> {
+    let c = if terms.len() < 32 {
+        4
+    } else if terms.len() < 512 {
+        6
+    } else {
+        8
+    };
+    let scalars: Vec<Vec<u8>> = terms.iter().map(|(k, _)| to_fixed_bytes(k, 32)).collect();
+    let mut acc: Option<GE> = None;
+    for window in (0..(256 + c - 1) / c).rev() {
+        for _ in 0..c {
+            acc = acc.and_then(|point| add_or_infinity(Some(point), &point));
+        }
+        let mut buckets: Vec<Option<GE>> = vec![None; (1 << c) - 1];
+        for (bytes, (_, point)) in scalars.iter().zip(terms) {
+            let digit = window_digit(bytes, window * c, c);
+            if digit != 0 {
+                buckets[digit - 1] = add_or_infinity(buckets[digit - 1], point);
+            }
+        }
+        // the running sum over the buckets from the top adds bucket d d times
+        let mut running: Option<GE> = None;
+        for bucket in buckets.iter().rev() {
+            if let Some(ref point) = *bucket {
+                running = add_or_infinity(running, point);
+            }
+            if let Some(ref point) = running {
+                acc = add_or_infinity(acc, point);
+            }
+        }
+    }
+    acc
+}
+
+// the c bit digit of a 32 byte big endian scalar that starts at bit position bit
+fn window_digit(bytes: &[u8], bit: usize, c: usize) -> usize {
+    (0..c)
+        .filter(|offset| bit + offset < 256)
+        .fold(0, |digit, offset| {
+            let i = bit + offset;
+            let set = (bytes[31 - i / 8] >> (i % 8)) & 1;
+            digit | ((set as usize) << offset)
+        })
+}
+
+// sum + point, with None for the point at infinity, which a GE can not hold and at which
+// curv's addition panics. P + Q is the point at infinity iff Q = -P, i.e. iff the compressed
+// encodings differ in the parity byte only.
+fn add_or_infinity(sum: Option<GE>, point: &GE) -> Option<GE> {
+    match sum {
+        None => Some(*point),
+        Some(acc) => {
+            let (acc_bytes, point_bytes) = (
+                acc.get_element().serialize(),
+                point.get_element().serialize(),
+            );
+            if acc_bytes[1..] == point_bytes[1..] && acc_bytes[0] != point_bytes[0] {
+                None
+            } else {
+                Some(acc.add_point(&point.get_element()))
+            }
+        }
+    }
+}
+
+#[deprecated(note = "use Signature::verify instead")]
+pub fn verify(
+    signature: &BigInt,
+    r_x: &BigInt,
+    apk: &GE,
+    message: &[u8],
+    musig_bit: bool,
+) -> Result<(), ProofError> {
+    verify_sig(signature, r_x, apk, message, musig_bit.into()).map_err(|_| ProofError)
+}
+
+// verify over untrusted encodings: 32 byte big endian s and r_x and a 33 or 65 byte SEC1 apk.
+// Any input of the wrong length or format is an error, never a panic.
+pub fn verify_bytes<M: Into<ChallengeMode>>(
+    signature: &[u8],
+    r_x: &[u8],
+    apk: &[u8],
+    message: &[u8],
+    mode: M,
+) -> Result<(), MuSigError> {
+    if signature.len() != 32 || r_x.len() != 32 {
+        return Err(MuSigError::InvalidEncoding);
+    }
+    let apk = PublicKey::from_bytes(apk)?;
+    verify_sig(
+        &BigInt::from(signature),
+        &BigInt::from(r_x),
+        apk.as_point(),
+        message,
+        mode.into(),
+    )
+}
+
+fn verify_sig(
+    signature: &BigInt,
+    r_x: &BigInt,
+    apk: &GE,
+    message: &[u8],
+    mode: ChallengeMode,
+) -> Result<(), MuSigError> {
+    verify_verbose(signature, r_x, apk, message, mode).map_err(MuSigError::from)
+}
+
+// an ephemeral public key and blind factor that do not open the commitment. party is the
+// public key of the sender if it is known.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommitmentError {
+    pub party: Option<GE>,
+    pub commitment: BigInt,
+}
+
+impl fmt::Display for CommitmentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.party {
+            Some(ref party) => write!(
+                f,
+                "party {} sent an opening that does not match its commitment {}",
+                party.bytes_compressed_to_big_int().to_hex(),
+                self.commitment.to_hex()
+            ),
+            None => write!(
+                f,
+                "opening does not match the commitment {}",
+                self.commitment.to_hex()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CommitmentError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VerifyError {
+    // x coordinate of sG - cY differs from the r_x of the signature
+    ChallengeMismatch { expected: BigInt, got: BigInt },
+    // sG - cY is the point at infinity
+    PointAtInfinity,
+    // s is not in [1, q)
+    InvalidScalar,
+    // r_x is not the x coordinate of a curve point
+    InvalidPoint,
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+// errors of the aggregated signature API
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MuSigError {
+    // the signature does not verify
+    InvalidSignature,
+    // an ephemeral public key and blind factor do not open the commitment, index is the
+    // position of the opening if several were checked
+    CommitmentMismatch { index: Option<usize> },
+    // no keys, nonces or partial signatures were given
+    EmptyKeySet,
+    // the party index is not a position in the key list
+    IndexOutOfRange,
+    // not a point on secp256k1 or the point at infinity
+    InvalidPoint,
+    // a private key or s outside its allowed range
+    InvalidScalar,
+    // a serialized value of the wrong length or format
+    InvalidEncoding,
+    // a nonce was presented a second time for the same key, see NonceTracker
+    NonceReuse,
+    // a public key appears more than once in a key list, or a party index in a list of shares
+    DuplicateKey,
+    // a SignerHandle used with another SignerSet than the one that issued it, or with the key
+    // pair of another member
+    HandleMismatch,
+    // fewer shares than the threshold t of a secret sharing
+    NotEnoughShares,
+    // a partial signature s_i does not verify against R_i, pk_i and a_i, see
+    // verify_partial_signature
+    InvalidPartialSignature,
+}
+
+impl fmt::Display for MuSigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::error::Error for MuSigError {}
+
+impl From<ProofError> for MuSigError {
+    fn from(_: ProofError) -> MuSigError {
+        MuSigError::InvalidSignature
+    }
+}
+
+impl From<CommitmentError> for MuSigError {
+    fn from(_: CommitmentError) -> MuSigError {
+        MuSigError::CommitmentMismatch { index: None }
+    }
+}
+
+impl From<VerifyError> for MuSigError {
+    fn from(error: VerifyError) -> MuSigError {
+        match error {
+            VerifyError::InvalidPoint => MuSigError::InvalidPoint,
+            VerifyError::InvalidScalar => MuSigError::InvalidScalar,
+            VerifyError::ChallengeMismatch { .. } | VerifyError::PointAtInfinity => {
+                MuSigError::InvalidSignature
+            }
+        }
+    }
+}
+
+// same check as verify, reporting why a signature was rejected
+pub fn verify_verbose<M: Into<ChallengeMode>>(
+    signature: &BigInt,
+    r_x: &BigInt,
+    apk: &GE,
+    message: &[u8],
+    mode: M,
+) -> Result<(), VerifyError> {
+    let base_point: GE = ECPoint::generator();
+    verify_with::<DefaultHash, _>(signature, r_x, apk, message, mode.into(), |s| {
+        base_point.scalar_mul(&s.get_element())
+    })
+    .map(|_| ())
+}
+
+// the checks of verify_verbose, with sG computed by base_mul. Returns the challenge hash c
+// the signature was checked against.
+fn verify_with<H: Hash, F: Fn(&FE) -> GE>(
+    signature: &BigInt,
+    r_x: &BigInt,
+    apk: &GE,
+    message: &[u8],
+    mode: ChallengeMode,
+    base_mul: F,
+) -> Result<BigInt, VerifyError> {
+    verify_with_muls::<H, _, _>(signature, r_x, apk, message, mode, base_mul, |c| {
+        apk.scalar_mul(&c.get_element())
+    })
+}
+
+// verify_with with c·apk computed by apk_mul
+fn verify_with_muls<H: Hash, F: Fn(&FE) -> GE, G: Fn(&FE) -> GE>(
+    signature: &BigInt,
+    r_x: &BigInt,
+    apk: &GE,
+    message: &[u8],
+    mode: ChallengeMode,
+    base_mul: F,
+    apk_mul: G,
+) -> Result<BigInt, VerifyError> {
+    // s = 0 means k = -c·a·x and r_x = 0 is never a curve point, both are rejected before
+    // any curve arithmetic
+    if *signature == BigInt::from(0) {
+        return Err(VerifyError::InvalidScalar);
+    }
+    if *r_x == BigInt::from(0) {
+        return Err(VerifyError::InvalidPoint);
+    }
+    if apk.x_coor().is_none() {
+        return Err(VerifyError::PointAtInfinity);
+    }
+    if !is_x_coor_on_curve(r_x) {
+        return Err(VerifyError::InvalidPoint);
+    }
+    if *signature >= curve_order() {
+        return Err(VerifyError::InvalidScalar);
+    }
+
+    let digest = compute_challenge_with_hash::<H>(r_x, apk, message, mode);
+
+    let signature_fe: FE = ECScalar::from(signature);
+    let sG = base_mul(&signature_fe);
+    let c: FE = ECScalar::from(&digest);
+    let cY = apk_mul(&c);
+    if sG == cY {
+        return Err(VerifyError::PointAtInfinity);
+    }
+    let sG = sG.sub_point(&cY.get_element());
+    match sG.x_coor() {
+        Some(ref x_coor) if constant_time_eq(x_coor, r_x) => Ok(digest),
+        Some(x_coor) => Err(VerifyError::ChallengeMismatch {
+            expected: r_x.clone(),
+            got: x_coor,
+        }),
+        None => Err(VerifyError::PointAtInfinity),
+    }
+}
+
+// The challenge hashes, which differ only in the first hash input. Musig is
+// c = H("musig/chal", r_x, apk, m), used for aggregated keys. MusigLegacy is c = H(0, r_x, apk, m),
+// the MuSig challenge of 0.3.x and earlier releases and of other implementations derived from
+// them: 0 is not taken from the MuSig paper, it was the first of the numeric prefixes 0, 1 and 2
+// that kept the challenge, key aggregation and nonce hashes apart before the string tags. Plain
+// is c = H(r_x, apk, m) for a single key, which stays untagged. It follows the shape of the
+// bip-schnorr challenge but is not the same hash: curv's HSha256 hashes the minimal big endian
+// bytes of each input, so an r_x or message with leading zero bytes is hashed shorter than the
+// 32 byte or raw encoding of bip-schnorr. Use bip340 for challenges that match the spec byte for
+// byte. true and false convert to Musig and Plain, the former musig_bit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChallengeMode {
+    Musig,
+    MusigLegacy,
+    Plain,
+}
+
+impl ChallengeMode {
+    // the hash input put in front of r_x, None for no prefix
+    pub fn prefix(self) -> Option<BigInt> {
+        match self {
+            ChallengeMode::Musig => Some(domain_tag(CHALLENGE_TAG, 0)),
+            ChallengeMode::MusigLegacy => Some(BigInt::from(0)),
+            ChallengeMode::Plain => None,
+        }
+    }
+}
+
+impl From<bool> for ChallengeMode {
+    fn from(musig_bit: bool) -> ChallengeMode {
+        if musig_bit {
+            ChallengeMode::Musig
+        } else {
+            ChallengeMode::Plain
+        }
+    }
+}
+
+// The hash of the key aggregation coefficients and the challenge. The *_with_hash variants
+// take another curv Hash, e.g. HSha512; all parties and the verifier must use the same one.
+pub type DefaultHash = HSha256;
+
+// the challenge of the given mode, shared by signing and verification
+fn compute_challenge(r_x: &BigInt, apk: &GE, message: &[u8], mode: ChallengeMode) -> BigInt {
+    compute_challenge_with_hash::<DefaultHash>(r_x, apk, message, mode)
+}
+
+// hash_0 for an arbitrary point r, already reduced to a scalar for the curve arithmetic
+pub fn compute_challenge_fe<M: Into<ChallengeMode>>(
+    r: &GE,
+    apk: &GE,
+    message: &[u8],
+    mode: M,
+) -> FE {
+    ECScalar::from(&EphemeralKey::hash_0(r, apk, message, mode))
+}
+
+// -c mod q, for checking sG - cP against R
+pub fn challenge_negate(c: &FE) -> FE {
+    negate_scalar(c)
+}
+
+fn compute_challenge_with_hash<H: Hash>(
+    r_x: &BigInt,
+    apk: &GE,
+    message: &[u8],
+    mode: ChallengeMode,
+) -> BigInt {
+    match mode.prefix() {
+        Some(prefix) => H::create_hash(&[
+            &prefix,
+            r_x,
+            &apk.bytes_compressed_to_big_int(),
+            &message_to_field_input(message),
+        ]),
+        None => H::create_hash(&[
+            r_x,
+            &apk.bytes_compressed_to_big_int(),
+            &message_to_field_input(message),
+        ]),
+    }
+}
+
+const KEY_AGG_TAG: &str = "musig/agg";
+const KEY_LIST_TAG: &str = "musig/keylist";
+const CHALLENGE_TAG: &str = "musig/chal";
+const NONCE_TAG: &str = "musig/nonce";
+const POP_TAG: &str = "musig/pop";
+const SEED_TAG: &str = "musig/seed";
+
+// the first hash input separating key aggregation, challenge and nonce hashes. With the
+// legacy_hashes feature this is the numeric prefix of 0.3.x and earlier versions, before the
+// tags were introduced, so that their signatures still verify.
+fn domain_tag(tag: &str, legacy_prefix: u32) -> BigInt {
+    if cfg!(feature = "legacy_hashes") {
+        BigInt::from(legacy_prefix)
+    } else {
+        BigInt::from(tag.as_bytes())
+    }
+}
+
+// The message as a hash input. The default encoding is ambiguous: it is BigInt::from(message),
+// which reads the bytes as a big endian number and so drops leading zero bytes, so messages
+// that differ only in leading zero bytes (e.g. [1] and [0, 0, 0, 1]) have the same challenge
+// and a signature of one is a signature of the other. It is kept as the default so that
+// signatures of 0.3.x (with legacy_hashes for the musig challenge) and the test vectors still
+// verify, see CHANGELOG.md. Callers signing messages that may start with zero bytes should
+// enable one of the features below. With the prefixed_msg
+// feature it is the integer with the bytes 01 || message, so the hash sees the message bytes
+// unchanged after a 01 byte. With the fixed_msg_hash feature, which takes precedence, it is
+// BigInt::from(SHA256(message)), so the hash input has the same length for every message.
+// Both features change every signature.
+pub fn message_to_field_input(message: &[u8]) -> BigInt {
+    if cfg!(feature = "fixed_msg_hash") {
+        BigInt::from(&Sha256::digest(message)[..])
+    } else if cfg!(feature = "prefixed_msg") {
+        let mut bytes = vec![1u8];
+        bytes.extend_from_slice(message);
+        BigInt::from(&bytes[..])
+    } else {
+        BigInt::from(message)
+    }
+}
+
+const KEY_AGG_BYTES: usize = 33 + 32 + 1;
+
+// the tweak of a KeyAgg without add_tweak, also the serde default
+fn zero_tweak() -> BigInt {
+    BigInt::from(0)
+}
+
+const SECP256K1_FIELD_PRIME: &str =
+    "fffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2f";
+
+fn field_prime() -> BigInt {
+    BigInt::from_str_radix(SECP256K1_FIELD_PRIME, 16).unwrap()
+}
+
+const SECP256K1_GROUP_ORDER: &str =
+    "fffffffffffffffffffffffffffffffebaaedce6af48a03bbfd25e8cd0364141";
+
+// q, the order of the secp256k1 group and the modulus of FE
+fn curve_order() -> BigInt {
+    BigInt::from_str_radix(SECP256K1_GROUP_ORDER, 16).unwrap()
+}
+
+// x is the x coordinate of a point on y^2 = x^3 + 7 iff x < p and x^3 + 7 is a square mod p.
+// This rejects x = 0 as 7 is not a square mod p.
+fn is_x_coor_on_curve(x: &BigInt) -> bool {
+    let p = field_prime();
+    if *x >= p {
+        return false;
+    }
+    let y_square = BigInt::mod_add(
+        &BigInt::mod_pow(x, &BigInt::from(3), &p),
+        &BigInt::from(7),
+        &p,
+    );
+    let euler_exponent = (&p - BigInt::from(1)) >> 1;
+    BigInt::mod_pow(&y_square, &euler_exponent, &p) == BigInt::from(1)
+}
+
+// the point with x coordinate x and the given parity of y, None if x is not on the curve
+fn lift_x_with_parity(x: &BigInt, odd_y: bool) -> Option<GE> {
+    if !is_x_coor_on_curve(x) {
+        return None;
+    }
+    let p = field_prime();
+    let y_square = BigInt::mod_add(
+        &BigInt::mod_pow(x, &BigInt::from(3), &p),
+        &BigInt::from(7),
+        &p,
+    );
+    // p = 3 mod 4, so a square root of y^2 is (y^2)^((p + 1) / 4)
+    let y = BigInt::mod_pow(&y_square, &((&p + BigInt::from(1)) >> 2), &p);
+    let y = if y.tstbit(0) != odd_y { &p - &y } else { y };
+    Some(GE::from_coor(x, &y))
+}
+
+// parses the 33 byte compressed encoding 02/03 || x
+fn point_from_compressed(bytes: &[u8]) -> Option<GE> {
+    if bytes.len() != 33 || (bytes[0] != 2 && bytes[0] != 3) {
+        return None;
+    }
+    lift_x_with_parity(&BigInt::from(&bytes[1..]), bytes[0] == 3)
+}
+
+// compares the big endian encodings of a and b, padded to a common width of at least 32 bytes,
+// without branching on the position of the first differing byte
+fn constant_time_eq(a: &BigInt, b: &BigInt) -> bool {
+    let len = cmp::max(
+        32,
+        cmp::max(BigInt::to_vec(a).len(), BigInt::to_vec(b).len()),
+    );
+    to_fixed_bytes(a, len)
+        .iter()
+        .zip(to_fixed_bytes(b, len).iter())
+        .fold(0u8, |acc, (a_byte, b_byte)| acc | (a_byte ^ b_byte))
+        == 0
+}
+
+fn negate_scalar(scalar: &FE) -> FE {
+    ECScalar::from(&(curve_order() - scalar.to_big_int()))
+}
+
+// the parity of y, which the x-only encodings of r_x, BIP340 keys and lift_x_with_parity drop
+pub fn point_has_even_y(point: &GE) -> bool {
+    !point.y_coor().unwrap().tstbit(0)
+}
+
+// -P = (x, p - y), the point with the same x and the other y parity
+pub fn negate_point(point: &GE) -> GE {
+    let y = point.y_coor().unwrap();
+    GE::from_coor(&point.x_coor().unwrap(), &(field_prime() - y))
+}
+
+fn os_rng() -> OsRng {
+    OsRng::new().expect("OS random number generator unavailable")
+}
+
+thread_local! {
+    // set while with_seeded_rng runs on this thread
+    static SEEDED_RNG: RefCell<Option<StdRng>> = RefCell::new(None);
+}
+
+// Runs f with all randomness of KeyPair::create, EphemeralKey::create and the other functions
+// that do not take an rng drawn from a StdRng seeded with seed instead of the OS rng, on
+// this thread only. This makes whole protocol runs reproducible for tests, never use it with
+// a seed that is not secret. Only built for the tests of this crate and with the test_utils
+// feature, so that a release build can not be switched to a fixed seed.
+#[cfg(any(test, feature = "test_utils"))]
+pub fn with_seeded_rng<T, F: FnOnce() -> T>(seed: [u8; 32], f: F) -> T {
+    use rand::SeedableRng;
+
+    struct Restore(Option<StdRng>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let previous = self.0.take();
+            SEEDED_RNG.with(|rng| *rng.borrow_mut() = previous);
+        }
+    }
+    let previous = SEEDED_RNG.with(|rng| rng.replace(Some(StdRng::from_seed(seed))));
+    let _restore = Restore(previous);
+    f()
+}
+
+// the rng behind the functions without an rng argument
+enum DefaultRng {
+    Os(OsRng),
+    Seeded(StdRng),
+}
+
+impl RngCore for DefaultRng {
+    fn next_u32(&mut self) -> u32 {
+        match self {
+            DefaultRng::Os(rng) => rng.next_u32(),
+            DefaultRng::Seeded(rng) => rng.next_u32(),
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        match self {
+            DefaultRng::Os(rng) => rng.next_u64(),
+            DefaultRng::Seeded(rng) => rng.next_u64(),
+        }
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        match self {
+            DefaultRng::Os(rng) => rng.fill_bytes(dest),
+            DefaultRng::Seeded(rng) => rng.fill_bytes(dest),
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        match self {
+            DefaultRng::Os(rng) => rng.try_fill_bytes(dest),
+            DefaultRng::Seeded(rng) => rng.try_fill_bytes(dest),
+        }
+    }
+}
+
+impl CryptoRng for DefaultRng {}
+
+// calls f with the seeded rng of with_seeded_rng if one is set, the OS rng otherwise
+fn with_default_rng<T, F: FnOnce(&mut DefaultRng) -> T>(f: F) -> T {
+    match SEEDED_RNG.with(|rng| rng.borrow_mut().take()) {
+        Some(seeded) => {
+            let mut rng = DefaultRng::Seeded(seeded);
+            let result = f(&mut rng);
+            if let DefaultRng::Seeded(seeded) = rng {
+                SEEDED_RNG.with(|rng| *rng.borrow_mut() = Some(seeded));
+            }
+            result
+        }
+        None => f(&mut DefaultRng::Os(os_rng())),
+    }
+}
+
+// uniform scalar in [1, q) by rejection sampling 32 byte strings
+fn random_scalar<R: RngCore + CryptoRng>(rng: &mut R) -> FE {
+    let q = curve_order();
+    loop {
+        let mut bytes = [0u8; 32];
+        rng.fill_bytes(&mut bytes);
+        let candidate = BigInt::from(&bytes[..]);
+        if candidate != BigInt::from(0) && candidate < q {
+            return ECScalar::from(&candidate);
+        }
+    }
+}
+
+// 0 <= s < q
+fn is_reduced_scalar(s: &BigInt) -> bool {
+    *s >= BigInt::from(0) && *s < curve_order()
+}
+
+fn compressed_bytes(point: &GE) -> Vec<u8> {
+    to_fixed_bytes(&point.bytes_compressed_to_big_int(), 33)
+}
+
+// a non-negative n as a DER INTEGER: tag 02, length, minimal big endian bytes with a 00 in
+// front if the top bit is set. n < 2^256, so the length fits the short form.
+fn der_integer(n: &BigInt) -> Vec<u8> {
+    let mut content = BigInt::to_vec(n);
+    if content.first().map_or(true, |byte| byte & 0x80 != 0) {
+        content.insert(0, 0);
+    }
+    let mut bytes = vec![0x02, content.len() as u8];
+    bytes.extend(content);
+    bytes
+}
+
+// the value of a DER INTEGER of at most 32 value bytes at the start of bytes, and the bytes
+// after it
+fn parse_der_integer(bytes: &[u8]) -> Result<(BigInt, &[u8]), MuSigError> {
+    if bytes.len() < 2 || bytes[0] != 0x02 {
+        return Err(MuSigError::InvalidEncoding);
+    }
+    let len = bytes[1] as usize;
+    if len == 0 || len > 33 || bytes.len() < 2 + len {
+        return Err(MuSigError::InvalidEncoding);
+    }
+    let content = &bytes[2..2 + len];
+    // negative, or a leading 00 that is not needed for the sign
+    if content[0] & 0x80 != 0 || (len > 1 && content[0] == 0 && content[1] & 0x80 == 0) {
+        return Err(MuSigError::InvalidEncoding);
+    }
+    if len == 33 && content[0] != 0 {
+        return Err(MuSigError::InvalidEncoding);
+    }
+    Ok((BigInt::from(content), &bytes[2 + len..]))
+}
+
+// HMAC-SHA256 as in RFC 2104
+fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut block_key = [0u8; 64];
+    if key.len() > 64 {
+        block_key[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block_key[..key.len()].copy_from_slice(key);
+    }
+    let mut inner = Sha256::new();
+    inner.input(
+        &block_key
+            .iter()
+            .map(|byte| byte ^ 0x36)
+            .collect::<Vec<u8>>(),
+    );
+    inner.input(data);
+    let mut outer = Sha256::new();
+    outer.input(
+        &block_key
+            .iter()
+            .map(|byte| byte ^ 0x5c)
+            .collect::<Vec<u8>>(),
+    );
+    outer.input(&inner.result());
+    let mut mac = [0u8; 32];
+    mac.copy_from_slice(&outer.result());
+    mac
+}
+
+// big endian encoding of n, left padded with zeros to len bytes
+fn to_fixed_bytes(n: &BigInt, len: usize) -> Vec<u8> {
+    let bytes = BigInt::to_vec(n);
+    assert!(bytes.len() <= len);
+    let mut padded = vec![0u8; len - bytes.len()];
+    padded.extend_from_slice(&bytes);
+    padded
+}
+
+pub fn verify_partial(
+    signature: &FE,
+    r_x: &BigInt,
+    c: &FE,
+    a: &FE,
+    key_pub: &GE,
+) -> Result<(), MuSigError> {
+    let g: GE = ECPoint::generator();
+    let sG = g * signature;
+    let cY = key_pub * a * c;
+    let sG = sG.sub_point(&cY.get_element());
+    if sG.x_coor().unwrap().to_hex() == *r_x.to_hex() {
+        Ok(())
+    } else {
+        Err(MuSigError::InvalidSignature)
+    }
+}
+
+// checks the contribution of a single signer, s_i·G == R_i + c·a_i·pk_i, so an aggregator
+// can find the party that sent a bad partial signature. s_i outside [1, q) is InvalidScalar,
+// a share that does not verify InvalidPartialSignature. R_i + c·a_i·pk_i may be the point at
+// infinity for a crafted R_i and c·a_i may be zero, neither panics.
+pub fn verify_partial_signature(
+    s_i: &BigInt,
+    r_i: &GE,
+    pk_i: &GE,
+    a_i: &BigInt,
+    c: &BigInt,
+) -> Result<(), MuSigError> {
+    let q = curve_order();
+    if *s_i <= BigInt::from(0) || *s_i >= q {
+        return Err(MuSigError::InvalidScalar);
+    }
+    let g: GE = ECPoint::generator();
+    let s_fe: FE = ECScalar::from(s_i);
+    let ca = BigInt::mod_mul(c, a_i, &q);
+    let expected = if ca == BigInt::from(0) {
+        Some(*r_i)
+    } else {
+        let ca_fe: FE = ECScalar::from(&ca);
+        add_or_infinity(Some(*r_i), &(*pk_i * ca_fe))
+    };
+    match expected {
+        Some(ref point) if *point == g * s_fe => Ok(()),
+        _ => Err(MuSigError::InvalidPartialSignature),
+    }
+}
+
+// in the 2-of-2 flow each party checks the other's share s_other before adding it to its own,
+// since a wrong share would otherwise only show up as an invalid aggregate
+pub fn verify_partial_2of2(
+    s_other: &BigInt,
+    r_other: &GE,
+    pk_other: &GE,
+    c: &BigInt,
+    a_other: &BigInt,
+) -> Result<(), MuSigError> {
+    verify_partial_signature(s_other, r_other, pk_other, a_other, c)
 }
 
+pub mod adaptor;
+pub mod bip340;
+pub mod blind;
+pub mod dkg;
+pub mod half_agg;
+pub mod ias;
+pub mod keys;
+pub mod musig2;
+pub mod session;
 mod test;
+pub mod test_vectors;
+pub mod threshold;
@@ -0,0 +1,160 @@
+/*
+    Multisig Schnorr
+
+    Copyright 2018 by Kzen Networks
+
+    This file is part of Multisig Schnorr library
+    (https://github.com/KZen-networks/multisig-schnorr)
+
+    Multisig Schnorr is free software: you can redistribute
+    it and/or modify it under the terms of the GNU General Public
+    License as published by the Free Software Foundation, either
+    version 3 of the License, or (at your option) any later version.
+
+    @license GPL-3.0+ <https://github.com/KZen-networks/multisig-schnorr/blob/master/LICENSE>
+*/
+
+//! MuSig2: aggregated signing without the commitment round.
+//!
+//! Every party sends two nonces R_1 = k_1·G, R_2 = k_2·G. Once all nonces are known the
+//! parties compute b = H("musig/nonce", R_1, R_2, apk, m) over the aggregated nonces and use
+//! the effective nonce R = R_1 + b·R_2. The partial signature of party i is s_i = k_1 + b·k_2 + c·a_i·x_i
+//! with the musig challenge c = hash_0(R, apk, m, Musig), so the sum of the partial signatures
+//! verifies with `Signature::verify` and `ChallengeMode::Musig`.
+//!
+//! Key aggregation is the same as in the classic flow, see `KeyAgg::key_aggregation_n`.
+use curv::cryptographic_primitives::hashing::hash_sha256::HSha256;
+use curv::cryptographic_primitives::hashing::traits::*;
+use curv::elliptic::curves::traits::*;
+use curv::{BigInt, FE, GE};
+
+use protocols::aggsig::{
+    add_or_infinity, compute_challenge_fe, curve_order, domain_tag, message_to_field_input,
+    negate_point, ChallengeMode, KeyAgg, KeyPair, MuSigError, Signature, NONCE_TAG,
+};
+
+// the two secret nonces of one signing session, consumed by sign so they can not be reused
+#[derive(Debug)]
+pub struct SecretNonce {
+    k_1: KeyPair,
+    k_2: KeyPair,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PublicNonce {
+    pub r_1: GE,
+    pub r_2: GE,
+}
+
+impl SecretNonce {
+    pub fn create() -> SecretNonce {
+        SecretNonce {
+            k_1: KeyPair::create(),
+            k_2: KeyPair::create(),
+        }
+    }
+
+    // sent to all other parties
+    pub fn public_nonce(&self) -> PublicNonce {
+        PublicNonce {
+            r_1: self.k_1.public_key,
+            r_2: self.k_2.public_key,
+        }
+    }
+}
+
+impl PublicNonce {
+    // sums the nonces of all parties. As in EphemeralKey::aggregate_ephemeral_pub_keys a sum
+    // at infinity, which a party can force by sending the negated sum of the others, is
+    // rejected with InvalidPoint instead of panicking.
+    pub fn aggregate(nonces: &[PublicNonce]) -> Result<PublicNonce, MuSigError> {
+        let (head, tail) = match nonces.split_first() {
+            Some(split) => split,
+            None => return Err(MuSigError::EmptyKeySet),
+        };
+        let mut agg = *head;
+        for nonce in tail {
+            if nonce.r_1 == negate_point(&agg.r_1) || nonce.r_2 == negate_point(&agg.r_2) {
+                return Err(MuSigError::InvalidPoint);
+            }
+            agg = PublicNonce {
+                r_1: agg.r_1 + nonce.r_1,
+                r_2: agg.r_2 + nonce.r_2,
+            };
+        }
+        Ok(agg)
+    }
+}
+
+// b = H("musig/nonce", R_1, R_2, apk, m) over the aggregated nonces
+pub fn nonce_coefficient(agg_nonce: &PublicNonce, apk: &GE, message: &[u8]) -> FE {
+    let b = HSha256::create_hash(&[
+        &domain_tag(NONCE_TAG, 2),
+        &agg_nonce.r_1.bytes_compressed_to_big_int(),
+        &agg_nonce.r_2.bytes_compressed_to_big_int(),
+        &apk.bytes_compressed_to_big_int(),
+        &message_to_field_input(message),
+    ]);
+    ECScalar::from(&b)
+}
+
+// R = R_1 + b·R_2. R_1 = -b·R_2 puts R at infinity, which gives InvalidPoint instead of a
+// panic in curv, and so does b = 0, for which b·R_2 can not be computed.
+pub fn effective_nonce(
+    agg_nonce: &PublicNonce,
+    apk: &GE,
+    message: &[u8],
+) -> Result<GE, MuSigError> {
+    let b = nonce_coefficient(agg_nonce, apk, message);
+    if b.to_big_int() == BigInt::from(0) {
+        return Err(MuSigError::InvalidPoint);
+    }
+    add_or_infinity(Some(agg_nonce.r_1), &(agg_nonce.r_2 * b)).ok_or(MuSigError::InvalidPoint)
+}
+
+// s_i = k_1 + b·k_2 + c·a_i·x_i, key_agg.hash is the coefficient a_i of keypair
+pub fn sign(
+    secret_nonce: SecretNonce,
+    keypair: &KeyPair,
+    key_agg: &KeyAgg,
+    agg_nonce: &PublicNonce,
+    message: &[u8],
+) -> Result<BigInt, MuSigError> {
+    let b = nonce_coefficient(agg_nonce, &key_agg.apk, message);
+    let r = effective_nonce(agg_nonce, &key_agg.apk, message)?;
+    let c = compute_challenge_fe(&r, &key_agg.apk, message, ChallengeMode::Musig);
+    let a: FE = ECScalar::from(&key_agg.hash);
+    let s = secret_nonce.k_1.private_key.to_scalar()
+        + b * secret_nonce.k_2.private_key.to_scalar()
+        + c * keypair.private_key.to_scalar() * a;
+    Ok(s.to_big_int())
+}
+
+// s = sum of the s_i with the effective nonce R. No partial signatures gives EmptyKeySet, an
+// s_i outside [0, q) InvalidScalar.
+pub fn aggregate_partial_signatures(
+    partial_sigs: &[BigInt],
+    agg_nonce: &PublicNonce,
+    apk: &GE,
+    message: &[u8],
+) -> Result<Signature, MuSigError> {
+    if partial_sigs.is_empty() {
+        return Err(MuSigError::EmptyKeySet);
+    }
+    let q = curve_order();
+    if partial_sigs
+        .iter()
+        .any(|s_i| *s_i < BigInt::from(0) || *s_i >= q)
+    {
+        return Err(MuSigError::InvalidScalar);
+    }
+    let r = effective_nonce(agg_nonce, apk, message)?;
+    let s = partial_sigs.iter().fold(FE::zero(), |acc, s_i| {
+        let s_i: FE = ECScalar::from(s_i);
+        acc + s_i
+    });
+    Ok(Signature {
+        s: s.to_big_int(),
+        r_x: r.x_coor().unwrap(),
+    })
+}
@@ -0,0 +1,339 @@
+/*
+    Multisig Schnorr
+
+    Copyright 2018 by Kzen Networks
+
+    This file is part of Multisig Schnorr library
+    (https://github.com/KZen-networks/multisig-schnorr)
+
+    Multisig Schnorr is free software: you can redistribute
+    it and/or modify it under the terms of the GNU General Public
+    License as published by the Free Software Foundation, either
+    version 3 of the License, or (at your option) any later version.
+
+    @license GPL-3.0+ <https://github.com/KZen-networks/multisig-schnorr/blob/master/LICENSE>
+*/
+
+//! State machine driving one party through the {2,2} aggregated signing protocol.
+//!
+//! Every transition consumes the session and returns the session in its next state, so the
+//! rounds can only be run in order. For example the ephemeral key can not be revealed before
+//! the commitment of the counter party was received:
+//!
+//! ```compile_fail
+//! # extern crate multi_party_schnorr;
+//! # use multi_party_schnorr::protocols::aggsig::session::MuSigSession;
+//! # use multi_party_schnorr::protocols::aggsig::KeyPair;
+//! # fn main() {
+//! let party1_key = KeyPair::create();
+//! let party2_key = KeyPair::create();
+//! let session = MuSigSession::new(party1_key, &party2_key.public_key, b"message");
+//! let _reveal = session.second_message();
+//! # }
+//! ```
+use curv::{BigInt, GE};
+
+#[cfg(feature = "unsafe_no_commitment")]
+use protocols::aggsig::NoCommitment;
+use protocols::aggsig::{
+    ChallengeMode, CommitmentError, EphemeralKey, KeyAgg, KeyPair, MuSigError, Signature,
+};
+
+// round one message: the commitment to the ephemeral public key
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Round1Message {
+    pub commitment: BigInt,
+}
+
+// round two message: the opening of the commitment, the ephemeral public key and the blind
+// factor
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Round2Message {
+    pub r_pub: GE,
+    pub blind_factor: BigInt,
+}
+
+// Snapshot of a session in round one or two, to resume it with from_persisted after a
+// restart. It contains the private key and the ephemeral private key. A snapshot must be
+// resumed at most once: two partial signatures with the same nonce over different
+// challenges reveal the private key.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionState {
+    round: u8,
+    keypair: KeyPair,
+    other_pk: GE,
+    ephemeral_key: EphemeralKey,
+    message: Vec<u8>,
+    other_commitment: Option<BigInt>,
+}
+
+impl SessionState {
+    pub fn round(&self) -> u8 {
+        self.round
+    }
+
+    // the key pairs must match their public keys and the ephemeral key must open its own
+    // commitment
+    fn validate(&self, round: u8) -> Result<(), MuSigError> {
+        if self.round != round || self.other_commitment.is_some() != (round == 2) {
+            return Err(MuSigError::InvalidEncoding);
+        }
+        for keypair in &[&self.keypair, &self.ephemeral_key.keypair] {
+            let restored = KeyPair::create_from_private_key(&keypair.expose_secret())?;
+            if restored.public_key != keypair.public_key {
+                return Err(MuSigError::InvalidPoint);
+            }
+        }
+        EphemeralKey::verify_commitment(
+            &self.ephemeral_key.keypair.public_key,
+            &self.ephemeral_key.blind_factor,
+            &self.ephemeral_key.commitment,
+        )?;
+        Ok(())
+    }
+}
+
+// the party committed to its ephemeral key and waits for the commitment of the counter party
+pub struct Round1;
+
+// commitments were exchanged, the party waits for the counter party to open its commitment
+pub struct Round2 {
+    other_commitment: BigInt,
+}
+
+// nonces were exchanged and the local partial signature is computed
+pub struct Finished {
+    r_tag: GE,
+    partial_sig: BigInt,
+}
+
+pub struct MuSigSession<S> {
+    keypair: KeyPair,
+    other_pk: GE,
+    ephemeral_key: EphemeralKey,
+    key_agg: KeyAgg,
+    message: Vec<u8>,
+    state: S,
+}
+
+impl MuSigSession<Round1> {
+    pub fn new(keypair: KeyPair, other_pk: &GE, message: &[u8]) -> MuSigSession<Round1> {
+        let key_agg = KeyAgg::key_aggregation(&keypair.public_key, other_pk);
+        MuSigSession {
+            keypair,
+            other_pk: *other_pk,
+            ephemeral_key: EphemeralKey::create(),
+            key_agg,
+            message: message.to_vec(),
+            state: Round1,
+        }
+    }
+
+    pub fn persist(self) -> SessionState {
+        SessionState {
+            round: 1,
+            keypair: self.keypair,
+            other_pk: self.other_pk,
+            ephemeral_key: self.ephemeral_key,
+            message: self.message,
+            other_commitment: None,
+        }
+    }
+
+    pub fn from_persisted(state: SessionState) -> Result<MuSigSession<Round1>, MuSigError> {
+        state.validate(1)?;
+        let key_agg = KeyAgg::key_aggregation(&state.keypair.public_key, &state.other_pk);
+        Ok(MuSigSession {
+            keypair: state.keypair,
+            other_pk: state.other_pk,
+            ephemeral_key: state.ephemeral_key,
+            key_agg,
+            message: state.message,
+            state: Round1,
+        })
+    }
+
+    // commitment to the local ephemeral public key, sent to the counter party
+    pub fn first_message(&self) -> Round1Message {
+        Round1Message {
+            commitment: self.ephemeral_key.commitment.clone(),
+        }
+    }
+
+    pub fn receive_commitment(self, message: &Round1Message) -> MuSigSession<Round2> {
+        MuSigSession {
+            keypair: self.keypair,
+            other_pk: self.other_pk,
+            ephemeral_key: self.ephemeral_key,
+            key_agg: self.key_agg,
+            message: self.message,
+            state: Round2 {
+                other_commitment: message.commitment.clone(),
+            },
+        }
+    }
+}
+
+impl MuSigSession<Round2> {
+    pub fn persist(self) -> SessionState {
+        SessionState {
+            round: 2,
+            keypair: self.keypair,
+            other_pk: self.other_pk,
+            ephemeral_key: self.ephemeral_key,
+            message: self.message,
+            other_commitment: Some(self.state.other_commitment),
+        }
+    }
+
+    pub fn from_persisted(state: SessionState) -> Result<MuSigSession<Round2>, MuSigError> {
+        state.validate(2)?;
+        let key_agg = KeyAgg::key_aggregation(&state.keypair.public_key, &state.other_pk);
+        Ok(MuSigSession {
+            keypair: state.keypair,
+            other_pk: state.other_pk,
+            ephemeral_key: state.ephemeral_key,
+            key_agg,
+            message: state.message,
+            state: Round2 {
+                other_commitment: state.other_commitment.unwrap(),
+            },
+        })
+    }
+
+    // opening of the commitment, sent to the counter party
+    pub fn second_message(&self) -> Round2Message {
+        Round2Message {
+            r_pub: self.ephemeral_key.keypair.public_key,
+            blind_factor: self.ephemeral_key.blind_factor.clone(),
+        }
+    }
+
+    pub fn receive_reveal(
+        self,
+        message: &Round2Message,
+    ) -> Result<MuSigSession<Finished>, CommitmentError> {
+        let other_r = &message.r_pub;
+        if let Err(mut error) = EphemeralKey::verify_commitment(
+            other_r,
+            &message.blind_factor,
+            &self.state.other_commitment,
+        ) {
+            error.party = Some(self.other_pk);
+            return Err(error);
+        }
+        let r_tag =
+            EphemeralKey::add_ephemeral_pub_keys(&self.ephemeral_key.keypair.public_key, other_r);
+        let c = EphemeralKey::hash_0(
+            &r_tag,
+            &self.key_agg.apk,
+            &self.message,
+            ChallengeMode::Musig,
+        );
+        let partial_sig =
+            EphemeralKey::sign(&self.ephemeral_key, &c, &self.keypair, &self.key_agg.hash);
+        Ok(MuSigSession {
+            keypair: self.keypair,
+            other_pk: self.other_pk,
+            ephemeral_key: self.ephemeral_key,
+            key_agg: self.key_agg,
+            message: self.message,
+            state: Finished { r_tag, partial_sig },
+        })
+    }
+}
+
+impl MuSigSession<Finished> {
+    // the local partial signature, sent to the counter party
+    pub fn partial_signature(&self) -> BigInt {
+        self.state.partial_sig.clone()
+    }
+
+    pub fn apk(&self) -> GE {
+        self.key_agg.apk
+    }
+
+    // combines both partial signatures and checks the result against the aggregated key
+    pub fn finalize(self, other_partial_sig: &BigInt) -> Result<Signature, MuSigError> {
+        let signature = EphemeralKey::add_signature_parts(
+            self.state.partial_sig,
+            other_partial_sig,
+            &self.state.r_tag,
+        )?;
+        signature.verify(&self.key_agg.apk, &self.message, ChallengeMode::Musig)?;
+        Ok(signature)
+    }
+}
+
+// INSECURE two party session without the commitment round: the parties exchange their
+// ephemeral public keys directly, so a malicious counter party can forge signatures with the
+// attack of Drijvers et al. Only for trusted test harnesses where both parties run in one
+// process, use MuSigSession everywhere else.
+#[cfg(feature = "unsafe_no_commitment")]
+pub struct DirectSession {
+    keypair: KeyPair,
+    ephemeral_key: EphemeralKey<NoCommitment>,
+    key_agg: KeyAgg,
+    message: Vec<u8>,
+}
+
+#[cfg(feature = "unsafe_no_commitment")]
+impl DirectSession {
+    pub fn new(keypair: KeyPair, other_pk: &GE, message: &[u8]) -> DirectSession {
+        let key_agg = KeyAgg::key_aggregation(&keypair.public_key, other_pk);
+        DirectSession {
+            keypair,
+            ephemeral_key: EphemeralKey::create_no_commit(),
+            key_agg,
+            message: message.to_vec(),
+        }
+    }
+
+    // the ephemeral public key, sent to the counter party as is
+    pub fn nonce(&self) -> GE {
+        self.ephemeral_key.keypair.public_key
+    }
+
+    pub fn receive_nonce(self, other_r: &GE) -> DirectSigned {
+        let r_tag =
+            EphemeralKey::add_ephemeral_pub_keys(&self.ephemeral_key.keypair.public_key, other_r);
+        let c = EphemeralKey::hash_0(
+            &r_tag,
+            &self.key_agg.apk,
+            &self.message,
+            ChallengeMode::Musig,
+        );
+        let partial_sig =
+            EphemeralKey::sign(&self.ephemeral_key, &c, &self.keypair, &self.key_agg.hash);
+        DirectSigned {
+            key_agg: self.key_agg,
+            message: self.message,
+            r_tag,
+            partial_sig,
+        }
+    }
+}
+
+// a DirectSession after the nonce exchange, holding the local partial signature
+#[cfg(feature = "unsafe_no_commitment")]
+pub struct DirectSigned {
+    key_agg: KeyAgg,
+    message: Vec<u8>,
+    r_tag: GE,
+    partial_sig: BigInt,
+}
+
+#[cfg(feature = "unsafe_no_commitment")]
+impl DirectSigned {
+    pub fn partial_signature(&self) -> BigInt {
+        self.partial_sig.clone()
+    }
+
+    // like MuSigSession::finalize
+    pub fn finalize(self, other_partial_sig: &BigInt) -> Result<Signature, MuSigError> {
+        let signature =
+            EphemeralKey::add_signature_parts(self.partial_sig, other_partial_sig, &self.r_tag)?;
+        signature.verify(&self.key_agg.apk, &self.message, ChallengeMode::Musig)?;
+        Ok(signature)
+    }
+}
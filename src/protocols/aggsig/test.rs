@@ -17,10 +17,47 @@
 
 #[cfg(test)]
 mod tests {
+    use curv::cryptographic_primitives::commitments::pedersen_commitment::PedersenCommitment;
+    use curv::cryptographic_primitives::hashing::hash_sha256::HSha256;
+    use curv::cryptographic_primitives::hashing::hash_sha512::HSha512;
+    use curv::cryptographic_primitives::hashing::traits::Hash;
     use curv::BigInt;
+    use curv::FE;
     use curv::GE;
-    use protocols::aggsig::{verify, verify_partial, EphemeralKey, KeyAgg, KeyPair};
+    use protocols::aggsig::adaptor;
+    use protocols::aggsig::bip340;
+    use protocols::aggsig::blind;
+    use protocols::aggsig::dkg;
+    use protocols::aggsig::half_agg;
+    use protocols::aggsig::ias;
+    use protocols::aggsig::keys::{PrivateKey, PublicKey};
+    use protocols::aggsig::musig2::{self, PublicNonce, SecretNonce};
+    use protocols::aggsig::session::{
+        MuSigSession, Round1, Round1Message, Round2, Round2Message, SessionState,
+    };
+    use protocols::aggsig::test_vectors::{self, TEST_VECTORS};
+    use protocols::aggsig::threshold::{reconstruct_secret, ThresholdKeyPair, ThresholdParams};
+    use protocols::aggsig::{
+        batch_equation_holds, compute_challenge, constant_time_eq, curve_order, domain_tag,
+        hmac_sha256, message_to_field_input, multi_scalar_mul, negate_point, negate_scalar,
+        point_has_even_y, verify_with,
+    };
+    use protocols::aggsig::{
+        batch_verify, challenge_negate, compute_challenge_fe, verify_bytes, verify_multisig,
+        verify_partial, verify_partial_2of2, verify_partial_signature, verify_possession,
+        verify_verbose, verify_xonly, with_seeded_rng, CachedVerifier, ChallengeMode,
+        CommitmentError, CommitmentScheme, EphemeralKey, KeyAgg, KeyAggBuilder, KeyPair,
+        MessageHasher, MuSigError, NonceTracker, PartialSignature, Signature, SignerSet,
+        SigningContext, Verifier, VerifyError,
+    };
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::collections::HashMap;
+    use std::mem::ManuallyDrop;
+    use std::ptr;
     extern crate hex;
+    extern crate serde_json;
+    use curv::arithmetic::traits::{Converter, Modulo};
     use curv::elliptic::curves::traits::*;
 
     #[test]
@@ -56,8 +93,10 @@ mod tests {
         let mut pks: Vec<GE> = Vec::new();
         pks.push(party1_key.public_key.clone());
         pks.push(party2_key.public_key.clone());
-        let party1_key_agg = KeyAgg::key_aggregation_n(&pks, 0);
-        let party2_key_agg = KeyAgg::key_aggregation_n(&pks, 1);
+        let party1_index = KeyAgg::sorted_position(&pks, &party1_key.public_key).unwrap();
+        let party2_index = KeyAgg::sorted_position(&pks, &party2_key.public_key).unwrap();
+        let party1_key_agg = KeyAgg::key_aggregation_n(&pks, party1_index).unwrap();
+        let party2_key_agg = KeyAgg::key_aggregation_n(&pks, party2_index).unwrap();
         assert_eq!(party1_key_agg.apk, party2_key_agg.apk);
 
         // compute R' = R1+R2:
@@ -106,10 +145,13 @@ mod tests {
         .is_ok());
 
         // signature s:
-        let (r, s) = EphemeralKey::add_signature_parts(s1, &s2, &party1_r_tag);
+        let signature = EphemeralKey::add_signature_parts(s1, &s2, &party1_r_tag).unwrap();
+        assert_eq!(signature.r_x, party1_r_tag.x_coor().unwrap());
 
         // verify:
-        assert!(verify(&s, &r, &party1_key_agg.apk, &message, is_musig,).is_ok())
+        assert!(signature
+            .verify(&party1_key_agg.apk, &message, is_musig)
+            .is_ok())
     }
 
     #[test]
@@ -136,16 +178,20 @@ mod tests {
         );
 
         // signature s:
-        let (R, s) = EphemeralKey::add_signature_parts(
+        let signature = EphemeralKey::add_signature_parts(
             s_tag,
             &BigInt::from(0),
             &party1_ephemeral_key.keypair.public_key,
-        );
+        )
+        .unwrap();
         // verify:
-        assert!(verify(&s, &R, &party1_key.public_key, &message, is_musig).is_ok());
+        assert!(signature
+            .verify(&party1_key.public_key, &message, is_musig)
+            .is_ok());
     }
 
-    //this test works only for curvesecp256k1
+    //this test works only for curvesecp256k1 and the default message encoding
+    #[cfg(not(any(feature = "prefixed_msg", feature = "fixed_msg_hash")))]
     #[test]
     fn test_schnorr_bip_test_vector_2() {
         let private_key_raw = "B7E151628AED2A6ABF7158809CF4F3C762E7160F38B4DA56A784D9045190CFEF";
@@ -156,7 +202,8 @@ mod tests {
         let message = hex::decode(message_raw).unwrap();
         let party1_key = KeyPair::create_from_private_key(
             &BigInt::from_str_radix(&private_key_raw, 16).unwrap(),
-        );
+        )
+        .unwrap();
         let party1_ephemeral_key = EphemeralKey::create_from_private_key(&party1_key, &message);
 
         // compute c = H0(Rtag || apk || message)
@@ -176,21 +223,3509 @@ mod tests {
         );
 
         // signature s:
-        let (R, s) = EphemeralKey::add_signature_parts(
+        let signature = EphemeralKey::add_signature_parts(
             s_tag,
             &BigInt::from(0),
             &party1_ephemeral_key.keypair.public_key,
-        );
+        )
+        .unwrap();
 
         let test_vector_R =
             "2a298dacae57395a15d0795ddbfd1dcb564da82b0f269bc70a74f8220429ba1d".to_string();
         let test_vector_s =
             "1e51a22ccec35599b8f266912281f8365ffc2d035a230434a1a64dc59f7013fd".to_string();
-        let sig_R = R.to_str_radix(16);
-        let sig_s = s.to_str_radix(16);
+        let sig_R = signature.r_x.to_str_radix(16);
+        let sig_s = signature.s.to_str_radix(16);
         assert_eq!(test_vector_R, sig_R);
         assert_eq!(test_vector_s, sig_s);
         // verify:
-        assert!(verify(&s, &R, &party1_key.public_key, &message, is_musig).is_ok())
+        assert!(signature
+            .verify(&party1_key.public_key, &message, is_musig)
+            .is_ok())
+    }
+
+    #[test]
+    fn test_serde_round_trip() {
+        let party1_key = KeyPair::create();
+        let party2_key = KeyPair::create();
+        let pks = vec![party1_key.public_key, party2_key.public_key];
+        let party1_key_agg = KeyAgg::key_aggregation_n(&pks, 0).unwrap();
+        let party1_ephemeral_key = EphemeralKey::create();
+
+        let key_pair_json = serde_json::to_string(&party1_key).unwrap();
+        let key_pair: KeyPair = serde_json::from_str(&key_pair_json).unwrap();
+        assert_eq!(key_pair.public_key, party1_key.public_key);
+
+        let key_agg_json = serde_json::to_string(&party1_key_agg).unwrap();
+        let key_agg: KeyAgg = serde_json::from_str(&key_agg_json).unwrap();
+        assert_eq!(key_agg.apk, party1_key_agg.apk);
+        assert_eq!(key_agg.hash, party1_key_agg.hash);
+
+        let ephemeral_key_json = serde_json::to_string(&party1_ephemeral_key).unwrap();
+        let ephemeral_key: EphemeralKey = serde_json::from_str(&ephemeral_key_json).unwrap();
+        assert_eq!(ephemeral_key.commitment, party1_ephemeral_key.commitment);
+        assert_eq!(
+            ephemeral_key.blind_factor,
+            party1_ephemeral_key.blind_factor
+        );
+        assert!(EphemeralKey::test_com(
+            &ephemeral_key.keypair.public_key,
+            &ephemeral_key.blind_factor,
+            &party1_ephemeral_key.commitment
+        ));
+    }
+
+    #[test]
+    fn test_musig_session_two_parties() {
+        let message: [u8; 4] = [79, 77, 69, 82];
+        let party1_key = KeyPair::create();
+        let party2_key = KeyPair::create();
+        let party1_pk = party1_key.public_key;
+        let party2_pk = party2_key.public_key;
+
+        let party1_session = MuSigSession::new(party1_key, &party2_pk, &message);
+        let party2_session = MuSigSession::new(party2_key, &party1_pk, &message);
+
+        // round 1: exchange commitments
+        let party1_commitment = party1_session.first_message();
+        let party2_commitment = party2_session.first_message();
+        let party1_session = party1_session.receive_commitment(&party2_commitment);
+        let party2_session = party2_session.receive_commitment(&party1_commitment);
+
+        // round 2: open commitments
+        let party1_reveal = party1_session.second_message();
+        let party2_reveal = party2_session.second_message();
+        let party1_session = party1_session.receive_reveal(&party2_reveal).unwrap();
+        let party2_session = party2_session.receive_reveal(&party1_reveal).unwrap();
+        assert_eq!(party1_session.apk(), party2_session.apk());
+
+        // round 3: exchange partial signatures
+        let party1_s = party1_session.partial_signature();
+        let party2_s = party2_session.partial_signature();
+        let apk = party1_session.apk();
+        let party1_signature = party1_session.finalize(&party2_s).unwrap();
+        let party2_signature = party2_session.finalize(&party1_s).unwrap();
+        assert_eq!(party1_signature, party2_signature);
+        assert!(party1_signature.verify(&apk, &message, true).is_ok());
+    }
+
+    #[test]
+    fn test_musig_session_rejects_wrong_reveal() {
+        let message: [u8; 4] = [79, 77, 69, 82];
+        let party1_key = KeyPair::create();
+        let party2_key = KeyPair::create();
+        let party1_pk = party1_key.public_key;
+        let party2_pk = party2_key.public_key;
+
+        let party1_session = MuSigSession::new(party1_key, &party2_pk, &message);
+        let party2_session = MuSigSession::new(party2_key, &party1_pk, &message);
+        let party1_commitment = party1_session.first_message();
+        let party2_commitment = party2_session.first_message();
+        let party1_session = party1_session.receive_commitment(&party2_commitment);
+        let party2_session = party2_session.receive_commitment(&party1_commitment);
+
+        // party2 opens its commitment with an ephemeral key it did not commit to
+        let mut party2_reveal = party2_session.second_message();
+        party2_reveal.r_pub = EphemeralKey::create().keypair.public_key;
+        let result = party1_session.receive_reveal(&party2_reveal);
+        let error = result.err().unwrap();
+        assert_eq!(error.party, Some(party2_pk));
+        assert_eq!(error.commitment, party2_commitment.commitment);
+    }
+
+    fn threshold_keygen(params: &ThresholdParams) -> Vec<ThresholdKeyPair> {
+        let party_keys = (0..params.n)
+            .map(|_| KeyPair::create())
+            .collect::<Vec<KeyPair>>();
+        let (vss_vec, shares_vec): (Vec<_>, Vec<_>) = party_keys
+            .iter()
+            .map(|key| key.share_private_key(params))
+            .unzip();
+        (0..params.n)
+            .map(|i| {
+                let received_shares = shares_vec
+                    .iter()
+                    .map(|shares| shares[i])
+                    .collect::<Vec<FE>>();
+                ThresholdKeyPair::construct(params, i, &vss_vec, &received_shares).unwrap()
+            })
+            .collect()
+    }
+
+    fn threshold_sign_and_verify(params: &ThresholdParams, signers: &[usize]) {
+        threshold_sign_and_verify_with_keys(&threshold_keygen(params), signers);
+    }
+
+    fn threshold_sign_and_verify_with_keys(keys: &[ThresholdKeyPair], signers: &[usize]) {
+        let is_musig = false;
+        let message: [u8; 4] = [79, 77, 69, 82];
+        let group_public_key = keys[0].group_public_key;
+        assert!(keys.iter().all(|k| k.group_public_key == group_public_key));
+
+        let ephemeral_keys = signers
+            .iter()
+            .map(|_| EphemeralKey::create())
+            .collect::<Vec<EphemeralKey>>();
+        let r_tag = ephemeral_keys[1..]
+            .iter()
+            .fold(ephemeral_keys[0].keypair.public_key, |acc, eph| {
+                EphemeralKey::add_ephemeral_pub_keys(&acc, &eph.keypair.public_key)
+            });
+        let c = EphemeralKey::hash_0(&r_tag, &group_public_key, &message, is_musig);
+
+        let partial_sigs = signers
+            .iter()
+            .zip(&ephemeral_keys)
+            .map(|(&i, eph)| keys[i].sign(eph, &c, signers))
+            .collect::<Vec<BigInt>>();
+        let signature = partial_sigs[1..].iter().fold(
+            EphemeralKey::add_signature_parts(partial_sigs[0].clone(), &BigInt::from(0), &r_tag)
+                .unwrap(),
+            |acc, s| EphemeralKey::add_signature_parts(acc.s, s, &r_tag).unwrap(),
+        );
+        assert!(signature
+            .verify(&group_public_key, &message, is_musig)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_threshold_2_of_3() {
+        let params = ThresholdParams { t: 2, n: 3 };
+        threshold_sign_and_verify(&params, &[0, 2]);
+        threshold_sign_and_verify(&params, &[1, 2]);
+    }
+
+    #[test]
+    fn test_threshold_3_of_5() {
+        let params = ThresholdParams { t: 3, n: 5 };
+        threshold_sign_and_verify(&params, &[0, 1, 4]);
+        threshold_sign_and_verify(&params, &[1, 2, 3]);
+    }
+
+    #[test]
+    fn test_threshold_n_of_n() {
+        let params = ThresholdParams { t: 3, n: 3 };
+        threshold_sign_and_verify(&params, &[0, 1, 2]);
+    }
+
+    fn sign_one_party(party1_key: &KeyPair, message: &[u8]) -> Signature {
+        let party1_ephemeral_key = EphemeralKey::create();
+        let party1_h_0 = EphemeralKey::hash_0(
+            &party1_ephemeral_key.keypair.public_key,
+            &party1_key.public_key,
+            message,
+            false,
+        );
+        let s_tag = EphemeralKey::sign(
+            &party1_ephemeral_key,
+            &party1_h_0,
+            party1_key,
+            &BigInt::from(1),
+        );
+        EphemeralKey::add_signature_parts(
+            s_tag,
+            &BigInt::from(0),
+            &party1_ephemeral_key.keypair.public_key,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_batch_verify() {
+        let mut batch = (0..5u8)
+            .map(|i| {
+                let key = KeyPair::create();
+                let message = vec![79, 77, 69, 82, i];
+                let signature = sign_one_party(&key, &message);
+                (signature, key.public_key, message, false)
+            })
+            .collect::<Vec<_>>();
+        assert!(batch_verify(&batch).is_ok());
+
+        batch[3].0.s = batch[3].0.s.clone() + BigInt::from(1);
+        assert!(batch_verify(&batch).is_err());
+    }
+
+    #[test]
+    fn test_multi_scalar_mul() {
+        let q = curve_order();
+        for n in [1usize, 3, 40, 600].iter() {
+            let terms: Vec<(BigInt, GE)> = (0..*n)
+                .map(|_| {
+                    (
+                        KeyPair::create().expose_secret(),
+                        KeyPair::create().public_key,
+                    )
+                })
+                .collect();
+            let expected = terms
+                .iter()
+                .map(|(k, point)| {
+                    let k: FE = ECScalar::from(k);
+                    *point * k
+                })
+                .fold(None, |acc: Option<GE>, term| match acc {
+                    Some(acc) => Some(acc + term),
+                    None => Some(term),
+                });
+            assert_eq!(multi_scalar_mul(&terms), expected);
+        }
+
+        // zero scalars add nothing, k·P + k·(-P) is the point at infinity
+        let point = KeyPair::create().public_key;
+        let k = KeyPair::create().expose_secret();
+        assert_eq!(
+            multi_scalar_mul(&[(BigInt::from(0), point), (BigInt::from(1), point)]),
+            Some(point)
+        );
+        assert_eq!(
+            multi_scalar_mul(&[(k.clone(), point), (k.clone(), negate_point(&point))]),
+            None
+        );
+        assert_eq!(
+            multi_scalar_mul(&[(k.clone(), point), (q - k, point)]),
+            None
+        );
+    }
+
+    #[test]
+    fn test_batch_verify_combined_check() {
+        let key = KeyPair::create();
+        let mut batch = (0..5u8)
+            .map(|i| {
+                let message = vec![79, 77, 69, 82, i];
+                let signature = sign_one_party_even_nonce(&key, &message);
+                (signature, key.public_key, message, false)
+            })
+            .collect::<Vec<_>>();
+        assert!(batch_equation_holds(&batch));
+        assert!(batch_verify(&batch).is_ok());
+
+        // an R with odd y fails the combined check, the per entry check still accepts it
+        let message = vec![1, 2, 3];
+        let ephemeral_key = loop {
+            let ephemeral_key = EphemeralKey::create();
+            if !point_has_even_y(&ephemeral_key.keypair.public_key) {
+                break ephemeral_key;
+            }
+        };
+        let r = ephemeral_key.keypair.public_key;
+        let c = EphemeralKey::hash_0(&r, &key.public_key, &message, false);
+        let s = EphemeralKey::sign(&ephemeral_key, &c, &key, &BigInt::from(1));
+        let signature = EphemeralKey::add_signature_parts(s, &BigInt::from(0), &r).unwrap();
+        batch.push((signature, key.public_key, message, false));
+        assert!(!batch_equation_holds(&batch));
+        assert!(batch_verify(&batch).is_ok());
+
+        // the error is the one of Signature::verify
+        batch[1].2 = vec![0xff];
+        assert!(!batch_equation_holds(&batch));
+        assert_eq!(
+            batch_verify(&batch),
+            batch[1].0.verify(&batch[1].1, &batch[1].2, false)
+        );
+        assert!(batch_verify(&[]).is_ok());
+    }
+
+    #[test]
+    fn test_key_aggregation_matches_key_aggregation_n() {
+        let party1_key = KeyPair::create();
+        let party2_key = KeyPair::create();
+        let pks = vec![party1_key.public_key, party2_key.public_key];
+
+        let party1_key_agg =
+            KeyAgg::key_aggregation(&party1_key.public_key, &party2_key.public_key);
+        let party1_index = KeyAgg::sorted_position(&pks, &party1_key.public_key).unwrap();
+        let party1_key_agg_n = KeyAgg::key_aggregation_n(&pks, party1_index).unwrap();
+        assert_eq!(party1_key_agg.apk, party1_key_agg_n.apk);
+        assert_eq!(party1_key_agg.hash, party1_key_agg_n.hash);
+    }
+
+    #[test]
+    fn test_deterministic_ephemeral_key() {
+        let message: [u8; 4] = [79, 77, 69, 82];
+        let party1_key = KeyPair::create();
+        let party2_key = KeyPair::create();
+        let apk = KeyAgg::aggregate_only(&[party1_key.public_key, party2_key.public_key]).unwrap();
+        let aux_rand: [u8; 32] = [7; 32];
+
+        let eph1 = EphemeralKey::create_deterministic(&party1_key, &apk, &message, None);
+        let eph2 = EphemeralKey::create_deterministic(&party1_key, &apk, &message, None);
+        assert_eq!(eph1.keypair.public_key, eph2.keypair.public_key);
+
+        let eph3 =
+            EphemeralKey::create_deterministic(&party1_key, &apk, &message, Some(&aux_rand[..]));
+        let eph4 =
+            EphemeralKey::create_deterministic(&party1_key, &apk, &message, Some(&aux_rand[..]));
+        assert_eq!(eph3.keypair.public_key, eph4.keypair.public_key);
+        assert_ne!(eph1.keypair.public_key, eph3.keypair.public_key);
+
+        let other_message: [u8; 4] = [79, 77, 69, 83];
+        let eph5 = EphemeralKey::create_deterministic(&party1_key, &apk, &other_message, None);
+        assert_ne!(eph1.keypair.public_key, eph5.keypair.public_key);
+
+        // another set of co-signers gives another nonce
+        let party3_key = KeyPair::create();
+        let other_apk =
+            KeyAgg::aggregate_only(&[party1_key.public_key, party3_key.public_key]).unwrap();
+        let eph6 = EphemeralKey::create_deterministic(&party1_key, &other_apk, &message, None);
+        assert_ne!(eph1.keypair.public_key, eph6.keypair.public_key);
+
+        // aux_rand is hashed as bytes, a leading zero byte makes a difference
+        let short_aux_rand: [u8; 31] = [7; 31];
+        let mut padded_aux_rand = [0u8; 32];
+        padded_aux_rand[1..].copy_from_slice(&short_aux_rand);
+        let eph7 = EphemeralKey::create_deterministic(
+            &party1_key,
+            &apk,
+            &message,
+            Some(&short_aux_rand[..]),
+        );
+        let eph8 = EphemeralKey::create_deterministic(
+            &party1_key,
+            &apk,
+            &message,
+            Some(&padded_aux_rand[..]),
+        );
+        assert_ne!(eph7.keypair.public_key, eph8.keypair.public_key);
+    }
+
+    #[test]
+    fn test_private_key_zeroed_on_drop() {
+        let mut party1_key = ManuallyDrop::new(KeyPair::create());
+        let mut party1_ephemeral_key = ManuallyDrop::new(EphemeralKey::create());
+        assert!(party1_key.private_key.to_scalar() != FE::zero());
+        assert!(party1_ephemeral_key.keypair.private_key.to_scalar() != FE::zero());
+
+        // run the destructors but keep the memory around to inspect it
+        unsafe {
+            ptr::drop_in_place(&mut *party1_key as *mut KeyPair);
+            ptr::drop_in_place(&mut *party1_ephemeral_key as *mut EphemeralKey);
+        }
+        assert!(party1_key.private_key.to_scalar() == FE::zero());
+        assert!(party1_ephemeral_key.keypair.private_key.to_scalar() == FE::zero());
+    }
+
+    #[test]
+    fn test_expose_secret_round_trip() {
+        let party1_key = KeyPair::create();
+        let secret = party1_key.expose_secret();
+        let restored_key = KeyPair::from_secret_scalar(ECScalar::from(&secret)).unwrap();
+        assert_eq!(restored_key.public_key, party1_key.public_key);
+        assert_eq!(restored_key.expose_secret(), secret);
+        assert_eq!(
+            KeyPair::from_secret_scalar(FE::zero()).unwrap_err(),
+            MuSigError::InvalidScalar
+        );
+    }
+
+    #[test]
+    fn test_verify_verbose_errors() {
+        let message: [u8; 4] = [79, 77, 69, 82];
+        let party1_key = KeyPair::create();
+        let signature = sign_one_party(&party1_key, &message);
+        let pk = party1_key.public_key;
+        assert!(verify_verbose(&signature.s, &signature.r_x, &pk, &message, false).is_ok());
+
+        // wrong message
+        let other_message: [u8; 4] = [79, 77, 69, 83];
+        match verify_verbose(&signature.s, &signature.r_x, &pk, &other_message, false) {
+            Err(VerifyError::ChallengeMismatch { expected, got }) => {
+                assert_eq!(expected, signature.r_x);
+                assert_ne!(got, signature.r_x);
+            }
+            _ => panic!("expected ChallengeMismatch"),
+        }
+
+        // s out of range
+        let s_plus_q = signature.s.clone() + FE::q();
+        assert_eq!(
+            verify_verbose(&s_plus_q, &signature.r_x, &pk, &message, false),
+            Err(VerifyError::InvalidScalar)
+        );
+
+        // s = c * x makes sG - cY the point at infinity
+        let r = EphemeralKey::create().keypair.public_key;
+        let c = EphemeralKey::hash_0(&r, &pk, &message, false);
+        let c_fe: FE = ECScalar::from(&c);
+        let s = (c_fe * party1_key.private_key.to_scalar()).to_big_int();
+        assert_eq!(
+            verify_verbose(&s, &r.x_coor().unwrap(), &pk, &message, false),
+            Err(VerifyError::PointAtInfinity)
+        );
+    }
+
+    #[test]
+    fn test_verify_rejects_invalid_r_x() {
+        // secp256k1 can not represent the point at infinity as a GE, so an identity apk never
+        // reaches verify; r_x values that are not x coordinates of a curve point are tested here
+        let message: [u8; 4] = [79, 77, 69, 82];
+        let party1_key = KeyPair::create();
+        let signature = sign_one_party(&party1_key, &message);
+        let pk = party1_key.public_key;
+
+        let field_prime = BigInt::from_str_radix(
+            "fffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2f",
+            16,
+        )
+        .unwrap();
+        // 0^3 + 7 and 5^3 + 7 are not squares mod p
+        for r_x in &[BigInt::from(0), BigInt::from(5), field_prime] {
+            assert_eq!(
+                verify_verbose(&signature.s, r_x, &pk, &message, false),
+                Err(VerifyError::InvalidPoint)
+            );
+            let bad_signature = Signature {
+                s: signature.s.clone(),
+                r_x: r_x.clone(),
+            };
+            assert!(bad_signature.verify(&pk, &message, false).is_err());
+        }
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        let party1_ephemeral_key = EphemeralKey::create();
+        let commitment = &party1_ephemeral_key.commitment;
+        assert!(constant_time_eq(commitment, &commitment.clone()));
+
+        // values differing in the lowest and in the highest bit of a 32 byte encoding
+        let low_bit_cleared = (commitment.clone() >> 1) << 1;
+        let low_bit_set = low_bit_cleared.clone() + BigInt::from(1);
+        assert!(!constant_time_eq(&low_bit_cleared, &low_bit_set));
+        let high_bit = BigInt::from(1) << 255;
+        assert!(!constant_time_eq(
+            &low_bit_cleared,
+            &(&low_bit_cleared + &high_bit)
+        ));
+
+        // a commitment with its lowest bit flipped does not open
+        let flipped_commitment = if *commitment == low_bit_cleared {
+            low_bit_set
+        } else {
+            low_bit_cleared
+        };
+        assert!(!EphemeralKey::test_com(
+            &party1_ephemeral_key.keypair.public_key,
+            &party1_ephemeral_key.blind_factor,
+            &flipped_commitment,
+        ));
+    }
+
+    #[test]
+    fn test_signature_bytes_round_trip() {
+        let message: [u8; 4] = [79, 77, 69, 82];
+        let party1_key = KeyPair::create();
+        let signature = sign_one_party(&party1_key, &message);
+
+        let bytes = signature.to_bytes();
+        let decoded = Signature::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded, signature);
+        assert!(decoded
+            .verify(&party1_key.public_key, &message, false)
+            .is_ok());
+
+        // s = q is out of range
+        let mut bytes = signature.to_bytes();
+        let q = BigInt::to_vec(&FE::q());
+        bytes[32..].copy_from_slice(&q);
+        assert!(Signature::from_bytes(&bytes).is_err());
+        let out_of_range = [0xffu8; 64];
+        assert!(Signature::from_bytes(&out_of_range).is_err());
+    }
+
+    #[test]
+    fn test_key_agg_builder() {
+        let pks: Vec<GE> = (0..4).map(|_| KeyPair::create().public_key).collect();
+        let mut builder = KeyAggBuilder::new();
+        for (i, pk) in pks.iter().enumerate() {
+            builder.add_key(pk);
+            for party_index in 0..=i {
+                let incremental = builder.finalize(party_index);
+                let key_agg = KeyAgg::key_aggregation_n(&pks[..=i], party_index).unwrap();
+                assert_eq!(incremental.apk, key_agg.apk);
+                assert_eq!(incremental.hash, key_agg.hash);
+            }
+        }
+        assert_eq!(builder.len(), pks.len());
+    }
+
+    #[test]
+    fn test_key_aggregation_n_order_independent() {
+        let keys: Vec<KeyPair> = (0..4).map(|_| KeyPair::create()).collect();
+        let pks: Vec<GE> = keys.iter().map(|key| key.public_key).collect();
+        let mut shuffled = pks.clone();
+        shuffled.reverse();
+        shuffled.swap(0, 2);
+
+        for pk in &pks {
+            let index = KeyAgg::sorted_position(&pks, pk).unwrap();
+            let shuffled_index = KeyAgg::sorted_position(&shuffled, pk).unwrap();
+            assert_eq!(index, shuffled_index);
+            let key_agg = KeyAgg::key_aggregation_n(&pks, index).unwrap();
+            let shuffled_key_agg = KeyAgg::key_aggregation_n(&shuffled, shuffled_index).unwrap();
+            assert_eq!(key_agg.apk, shuffled_key_agg.apk);
+            assert_eq!(key_agg.hash, shuffled_key_agg.hash);
+        }
+        let other_pk = KeyPair::create().public_key;
+        assert!(KeyAgg::sorted_position(&pks, &other_pk).is_none());
+    }
+
+    #[test]
+    fn test_aggregate_only() {
+        let pks: Vec<GE> = (0..3).map(|_| KeyPair::create().public_key).collect();
+        let apk = KeyAgg::aggregate_only(&pks).unwrap();
+        assert_eq!(apk, KeyAgg::key_aggregation_n(&pks, 0).unwrap().apk);
+        assert_eq!(apk, KeyAgg::key_aggregation_n(&pks, 2).unwrap().apk);
+
+        assert_eq!(KeyAgg::aggregate_only(&[]), Err(MuSigError::EmptyKeySet));
+        assert_eq!(
+            KeyAgg::aggregate_only(&[pks[0], pks[1], pks[0]]),
+            Err(MuSigError::DuplicateKey)
+        );
+    }
+
+    #[test]
+    fn test_musig2_three_parties() {
+        let message: [u8; 4] = [79, 77, 69, 82];
+        let keys: Vec<KeyPair> = (0..3).map(|_| KeyPair::create()).collect();
+        let pks: Vec<GE> = keys.iter().map(|key| key.public_key).collect();
+        let key_aggs: Vec<KeyAgg> = pks
+            .iter()
+            .map(|pk| {
+                KeyAgg::key_aggregation_n(&pks, KeyAgg::sorted_position(&pks, pk).unwrap()).unwrap()
+            })
+            .collect();
+        let apk = key_aggs[0].apk;
+
+        // single round: every party broadcasts its two public nonces
+        let secret_nonces: Vec<SecretNonce> = (0..3).map(|_| SecretNonce::create()).collect();
+        let public_nonces: Vec<PublicNonce> = secret_nonces
+            .iter()
+            .map(|nonce| nonce.public_nonce())
+            .collect();
+        let agg_nonce = PublicNonce::aggregate(&public_nonces).unwrap();
+
+        let partial_sigs: Vec<BigInt> = secret_nonces
+            .into_iter()
+            .zip(keys.iter().zip(&key_aggs))
+            .map(|(nonce, (key, key_agg))| {
+                musig2::sign(nonce, key, key_agg, &agg_nonce, &message).unwrap()
+            })
+            .collect();
+        let signature =
+            musig2::aggregate_partial_signatures(&partial_sigs, &agg_nonce, &apk, &message)
+                .unwrap();
+        assert!(signature.verify(&apk, &message, true).is_ok());
+        assert!(signature.verify(&apk, &[1, 2, 3], true).is_err());
+
+        assert_eq!(
+            musig2::aggregate_partial_signatures(&[], &agg_nonce, &apk, &message),
+            Err(MuSigError::EmptyKeySet)
+        );
+        let mut out_of_range = partial_sigs.clone();
+        out_of_range[1] = out_of_range[1].clone() + curve_order();
+        assert_eq!(
+            musig2::aggregate_partial_signatures(&out_of_range, &agg_nonce, &apk, &message),
+            Err(MuSigError::InvalidScalar)
+        );
+        out_of_range[1] = BigInt::from(-1);
+        assert_eq!(
+            musig2::aggregate_partial_signatures(&out_of_range, &agg_nonce, &apk, &message),
+            Err(MuSigError::InvalidScalar)
+        );
+    }
+
+    #[test]
+    fn test_musig2_aggregate_rejects_bad_nonces() {
+        assert_eq!(PublicNonce::aggregate(&[]), Err(MuSigError::EmptyKeySet));
+
+        // the last party cancels the first nonces of the others
+        let honest: Vec<PublicNonce> = (0..2)
+            .map(|_| SecretNonce::create().public_nonce())
+            .collect();
+        let sum = PublicNonce::aggregate(&honest).unwrap();
+        let malicious = PublicNonce {
+            r_1: negate_point(&sum.r_1),
+            r_2: SecretNonce::create().public_nonce().r_2,
+        };
+        assert_eq!(
+            PublicNonce::aggregate(&[honest[0], honest[1], malicious]),
+            Err(MuSigError::InvalidPoint)
+        );
+    }
+
+    #[test]
+    fn test_create_with_seeded_rng() {
+        let mut rng1 = StdRng::seed_from_u64(7);
+        let mut rng2 = StdRng::seed_from_u64(7);
+        let key1 = KeyPair::create_with_rng(&mut rng1);
+        let key2 = KeyPair::create_with_rng(&mut rng2);
+        assert_eq!(key1.public_key, key2.public_key);
+        assert_eq!(key1.expose_secret(), key2.expose_secret());
+
+        let ephemeral_key1 = EphemeralKey::create_with_rng(&mut rng1);
+        let ephemeral_key2 = EphemeralKey::create_with_rng(&mut rng2);
+        assert_eq!(
+            ephemeral_key1.keypair.public_key,
+            ephemeral_key2.keypair.public_key
+        );
+        assert_eq!(ephemeral_key1.commitment, ephemeral_key2.commitment);
+        assert_eq!(ephemeral_key1.blind_factor, ephemeral_key2.blind_factor);
+
+        let mut rng3 = StdRng::seed_from_u64(8);
+        let key3 = KeyPair::create_with_rng(&mut rng3);
+        assert_ne!(key1.public_key, key3.public_key);
+    }
+
+    fn decode_32(hex_str: &str) -> [u8; 32] {
+        let mut bytes = [0u8; 32];
+        bytes.copy_from_slice(&hex::decode(hex_str).unwrap());
+        bytes
+    }
+
+    fn decode_64(hex_str: &str) -> [u8; 64] {
+        let mut bytes = [0u8; 64];
+        bytes.copy_from_slice(&hex::decode(hex_str).unwrap());
+        bytes
+    }
+
+    // BIP340 test vectors 0, 1 and 4
+    #[test]
+    fn test_bip340_vectors() {
+        let vectors = [
+            (
+                "0000000000000000000000000000000000000000000000000000000000000003",
+                "F9308A019258C31049344F85F89D5229B531C845836F99B08601F113BCE036F9",
+                "0000000000000000000000000000000000000000000000000000000000000000",
+                "0000000000000000000000000000000000000000000000000000000000000000",
+                "E907831F80848D1069A5371B402410364BDF1C5F8307B0084C55F1CE2DCA8215\
+                 25F66A4A85EA8B71E482A74F382D2CE5EBEEE8FDB2172F477DF4900D310536C0",
+            ),
+            (
+                "B7E151628AED2A6ABF7158809CF4F3C762E7160F38B4DA56A784D9045190CFEF",
+                "DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA659",
+                "0000000000000000000000000000000000000000000000000000000000000001",
+                "243F6A8885A308D313198A2E03707344A4093822299F31D0082EFA98EC4E6C89",
+                "6896BD60EEAE296DB48A229FF71DFE071BDE413E6D43F917DC8DCF8C78DE3341\
+                 8906D11AC976ABCCB20B091292BFF4EA897EFCB639EA871CFA95F6DE339E4B0A",
+            ),
+        ];
+        for (secret_key, public_key, aux_rand, message, signature) in vectors.iter() {
+            let keypair =
+                KeyPair::create_from_private_key(&BigInt::from(&decode_32(secret_key)[..]))
+                    .unwrap();
+            let public_key = decode_32(public_key);
+            let message = decode_32(message);
+            let expected = decode_64(signature);
+            assert_eq!(bip340::xonly_bytes(&keypair.public_key), public_key);
+
+            let signature = bip340::sign(&keypair, &message, &decode_32(aux_rand));
+            assert_eq!(&signature.to_bytes()[..], &expected[..]);
+            assert!(bip340::verify(&signature, &public_key, &message).is_ok());
+        }
+
+        // r_x with leading zero bytes
+        let public_key =
+            decode_32("D69C3509BB99E412E68B0FE8544E72837DFA30746D8BE2AA65975F29D22DC7B9");
+        let message = decode_32("4DF3C3F68FCC83B27E9D42C90431A72499F17875C81A599B566C9889B9696703");
+        let signature = Signature::from_bytes(&decode_64(
+            "00000000000000000000003B78CE563F89A0ED9414F5AA28AD0D96D6795F9C63\
+             76AFB1548AF603B3EB45C9F8207DEE1060CB71C04E80F593060B07D28308D7F4",
+        ))
+        .unwrap();
+        assert!(bip340::verify(&signature, &public_key, &message).is_ok());
+        assert!(bip340::verify(&signature, &public_key, &[0u8; 32]).is_err());
+    }
+
+    #[test]
+    fn test_bip340_sign_verify() {
+        let message: [u8; 4] = [79, 77, 69, 82];
+        for _ in 0..4 {
+            let keypair = KeyPair::create();
+            let public_key = bip340::xonly_bytes(&keypair.public_key);
+            let signature = bip340::sign(&keypair, &message, &[7u8; 32]);
+            assert!(bip340::verify(&signature, &public_key, &message).is_ok());
+            let mut tampered = signature.clone();
+            tampered.s = tampered.s + BigInt::from(1);
+            assert!(bip340::verify(&tampered, &public_key, &message).is_err());
+        }
+    }
+
+    #[test]
+    fn test_create_from_private_key_range() {
+        assert!(KeyPair::create_from_private_key(&BigInt::from(0)).is_err());
+        assert!(KeyPair::create_from_private_key(&FE::q()).is_err());
+        assert!(KeyPair::create_from_private_key(&(FE::q() + BigInt::from(1))).is_err());
+
+        let q_minus_1 = FE::q() - BigInt::from(1);
+        let keypair = KeyPair::create_from_private_key(&q_minus_1).unwrap();
+        assert_eq!(keypair.expose_secret(), q_minus_1);
+        let keypair = KeyPair::create_from_private_key(&BigInt::from(1)).unwrap();
+        let generator: GE = ECPoint::generator();
+        assert_eq!(keypair.public_key, generator);
+    }
+
+    // runs the classic n-party flow up to the partial signatures. Returns the keys, the key
+    // aggregation of every party, the ephemeral keys, the challenge and the partial signatures.
+    fn n_party_partial_signatures(
+        n: usize,
+        message: &[u8],
+    ) -> (
+        Vec<KeyPair>,
+        Vec<KeyAgg>,
+        Vec<EphemeralKey>,
+        BigInt,
+        Vec<BigInt>,
+    ) {
+        let keys: Vec<KeyPair> = (0..n).map(|_| KeyPair::create()).collect();
+        let pks: Vec<GE> = keys.iter().map(|key| key.public_key).collect();
+        let key_aggs: Vec<KeyAgg> = pks
+            .iter()
+            .map(|pk| {
+                KeyAgg::key_aggregation_n(&pks, KeyAgg::sorted_position(&pks, pk).unwrap()).unwrap()
+            })
+            .collect();
+        let mut ephemeral_keys: Vec<EphemeralKey> =
+            (0..n).map(|_| EphemeralKey::create()).collect();
+        let rs: Vec<GE> = ephemeral_keys
+            .iter()
+            .map(|r| r.keypair.public_key)
+            .collect();
+        let (r_hat, negated) = EphemeralKey::aggregate_ephemeral_pub_keys(&rs).unwrap();
+        if negated {
+            for ephemeral_key in ephemeral_keys.iter_mut() {
+                ephemeral_key.negate();
+            }
+        }
+        let c = EphemeralKey::hash_0(&r_hat, &key_aggs[0].apk, message, true);
+        let partial_sigs: Vec<BigInt> = (0..n)
+            .map(|i| EphemeralKey::sign(&ephemeral_keys[i], &c, &keys[i], &key_aggs[i].hash))
+            .collect();
+        (keys, key_aggs, ephemeral_keys, c, partial_sigs)
+    }
+
+    #[test]
+    fn test_verify_partial_signature_flags_bad_share() {
+        let message: [u8; 4] = [79, 77, 69, 82];
+        let (keys, key_aggs, ephemeral_keys, c, mut partial_sigs) =
+            n_party_partial_signatures(3, &message);
+        let bad_party = 1;
+        partial_sigs[bad_party] = (partial_sigs[bad_party].clone() + BigInt::from(1)) % FE::q();
+
+        for i in 0..3 {
+            let is_valid = verify_partial_signature(
+                &partial_sigs[i],
+                &ephemeral_keys[i].keypair.public_key,
+                &keys[i].public_key,
+                &key_aggs[i].hash,
+                &c,
+            );
+            if i == bad_party {
+                assert_eq!(is_valid, Err(MuSigError::InvalidPartialSignature));
+            } else {
+                assert_eq!(is_valid, Ok(()));
+            }
+        }
+        assert_eq!(
+            verify_partial_signature(
+                &curve_order(),
+                &ephemeral_keys[0].keypair.public_key,
+                &keys[0].public_key,
+                &key_aggs[0].hash,
+                &c,
+            ),
+            Err(MuSigError::InvalidScalar)
+        );
+    }
+
+    #[test]
+    fn test_verify_partial_signature_at_infinity() {
+        let pk_i = KeyPair::create().public_key;
+        let a_i = BigInt::from(3);
+        let c = BigInt::from(5);
+        let s_i = BigInt::from(7);
+        let ca: FE = ECScalar::from(&BigInt::from(15));
+
+        // R_i = -(c·a_i)·pk_i puts R_i + c·a_i·pk_i at infinity
+        let r_i = negate_point(&(pk_i * ca));
+        assert_eq!(
+            verify_partial_signature(&s_i, &r_i, &pk_i, &a_i, &c),
+            Err(MuSigError::InvalidPartialSignature)
+        );
+
+        // c·a_i = 0 leaves s_i·G == R_i
+        let g: GE = ECPoint::generator();
+        let s_fe: FE = ECScalar::from(&s_i);
+        let r_i = g * s_fe;
+        assert_eq!(
+            verify_partial_signature(&s_i, &r_i, &pk_i, &a_i, &BigInt::from(0)),
+            Ok(())
+        );
+        assert_eq!(
+            verify_partial_signature(&s_i, &r_i, &pk_i, &curve_order(), &c),
+            Ok(())
+        );
+        assert_eq!(
+            verify_partial_signature(&s_i, &r_i, &pk_i, &a_i, &c),
+            Err(MuSigError::InvalidPartialSignature)
+        );
+    }
+
+    #[test]
+    fn test_aggregate_ephemeral_pub_keys() {
+        let rs: Vec<GE> = (0..3).map(|_| KeyPair::create().public_key).collect();
+        let (r_hat, negated) = EphemeralKey::aggregate_ephemeral_pub_keys(&rs).unwrap();
+        let r_hat_pairwise = EphemeralKey::add_ephemeral_pub_keys(
+            &EphemeralKey::add_ephemeral_pub_keys(&rs[0], &rs[1]),
+            &rs[2],
+        );
+        if negated {
+            assert_eq!(r_hat, negate_point(&r_hat_pairwise));
+        } else {
+            assert_eq!(r_hat, r_hat_pairwise);
+        }
+        assert!(bip340::has_even_y(&r_hat));
+        let (r_single, _) = EphemeralKey::aggregate_ephemeral_pub_keys(&rs[..1]).unwrap();
+        assert_eq!(r_single.x_coor(), rs[0].x_coor());
+        assert!(EphemeralKey::aggregate_ephemeral_pub_keys(&[]).is_err());
+    }
+
+    #[test]
+    fn test_aggregate_signature_parts() {
+        let message: [u8; 4] = [79, 77, 69, 82];
+        let (_keys, key_aggs, ephemeral_keys, _c, partial_sigs) =
+            n_party_partial_signatures(4, &message);
+        let rs: Vec<GE> = ephemeral_keys
+            .iter()
+            .map(|r| r.keypair.public_key)
+            .collect();
+        let (r_hat, _) = EphemeralKey::aggregate_ephemeral_pub_keys(&rs).unwrap();
+
+        let signature = EphemeralKey::aggregate_signature_parts(&partial_sigs, &r_hat).unwrap();
+        assert!(signature.verify(&key_aggs[0].apk, &message, true).is_ok());
+        assert!(signature.verify(&key_aggs[0].apk, &message, false).is_err());
+        assert!(EphemeralKey::aggregate_signature_parts(&[], &r_hat).is_err());
+    }
+
+    #[test]
+    fn test_hash_0_matches_verify_challenge() {
+        let message: [u8; 4] = [79, 77, 69, 82];
+        let apk = KeyPair::create().public_key;
+        let r = KeyPair::create().public_key;
+        for &musig_bit in [true, false].iter() {
+            assert_eq!(
+                EphemeralKey::hash_0(&r, &apk, &message, musig_bit),
+                compute_challenge(&r.x_coor().unwrap(), &apk, &message, musig_bit.into())
+            );
+        }
+    }
+
+    // signs with the two keys under key_aggs, party 0 adds the tweak
+    fn sign_tweaked(keys: &[KeyPair], key_aggs: &[KeyAgg], message: &[u8]) -> Signature {
+        let mut ephemeral_keys: Vec<EphemeralKey> =
+            (0..2).map(|_| EphemeralKey::create()).collect();
+        let rs: Vec<GE> = ephemeral_keys
+            .iter()
+            .map(|r| r.keypair.public_key)
+            .collect();
+        let (r_hat, negated) = EphemeralKey::aggregate_ephemeral_pub_keys(&rs).unwrap();
+        if negated {
+            for ephemeral_key in ephemeral_keys.iter_mut() {
+                ephemeral_key.negate();
+            }
+        }
+        let c = EphemeralKey::hash_0(&r_hat, &key_aggs[0].apk, message, true);
+        let partial_sigs = vec![
+            EphemeralKey::sign_with_tweak(
+                &ephemeral_keys[0],
+                &c,
+                &keys[0],
+                &key_aggs[0].hash,
+                &key_aggs[0].tweak,
+            ),
+            EphemeralKey::sign(&ephemeral_keys[1], &c, &keys[1], &key_aggs[1].hash),
+        ];
+        EphemeralKey::aggregate_signature_parts(&partial_sigs, &r_hat).unwrap()
+    }
+
+    #[test]
+    fn test_sign_with_tweaked_key() {
+        let message: [u8; 4] = [79, 77, 69, 82];
+        let keys: Vec<KeyPair> = (0..2).map(|_| KeyPair::create()).collect();
+        let pks: Vec<GE> = keys.iter().map(|key| key.public_key).collect();
+        let untweaked: Vec<KeyAgg> = pks
+            .iter()
+            .map(|pk| {
+                KeyAgg::key_aggregation_n(&pks, KeyAgg::sorted_position(&pks, pk).unwrap()).unwrap()
+            })
+            .collect();
+        let apk = untweaked[0].apk;
+
+        // try tweaks until apk + t·G has odd y, so that the tweaked key gets negated
+        let mut saw_odd_y = false;
+        let mut tweak = BigInt::from(12345);
+        while !saw_odd_y {
+            let base_point: GE = ECPoint::generator();
+            let tweak_fe: FE = ECScalar::from(&tweak);
+            saw_odd_y = !point_has_even_y(&(apk + base_point * tweak_fe));
+            let key_aggs: Vec<KeyAgg> = untweaked
+                .iter()
+                .map(|key_agg| key_agg.add_tweak(&tweak))
+                .collect();
+            let tweaked_apk = key_aggs[0].apk;
+            assert_ne!(tweaked_apk, apk);
+            assert!(point_has_even_y(&tweaked_apk));
+            assert_eq!(key_aggs[0].negated, untweaked[0].negated != saw_odd_y);
+
+            let signature = sign_tweaked(&keys, &key_aggs, &message);
+            assert!(signature.verify(&tweaked_apk, &message, true).is_ok());
+            assert!(signature.verify(&apk, &message, true).is_err());
+
+            // a second tweak on top of the first, and the tweak sum through to_hex
+            let twice: Vec<KeyAgg> = key_aggs
+                .iter()
+                .map(|key_agg| key_agg.add_tweak(&BigInt::from(7)))
+                .collect();
+            assert_eq!(
+                KeyAgg::from_hex(&twice[0].to_hex()).unwrap().tweak,
+                twice[0].tweak
+            );
+            let signature = sign_tweaked(&keys, &twice, &message);
+            assert!(signature.verify(&twice[0].apk, &message, true).is_ok());
+
+            tweak = tweak + BigInt::from(1);
+        }
+    }
+
+    // apk and R_hat are normalized to even y, so s·G == R_hat + c·apk holds as point equality
+    #[test]
+    fn test_even_y_normalization() {
+        let message: [u8; 4] = [79, 77, 69, 82];
+        let base_point: GE = ECPoint::generator();
+        for _ in 0..50 {
+            let (_keys, key_aggs, ephemeral_keys, c, partial_sigs) =
+                n_party_partial_signatures(3, &message);
+            let apk = key_aggs[0].apk;
+            assert!(bip340::has_even_y(&apk));
+            let rs: Vec<GE> = ephemeral_keys
+                .iter()
+                .map(|r| r.keypair.public_key)
+                .collect();
+            let (r_hat, negated) = EphemeralKey::aggregate_ephemeral_pub_keys(&rs).unwrap();
+            assert!(!negated);
+            assert!(bip340::has_even_y(&r_hat));
+
+            let signature = EphemeralKey::aggregate_signature_parts(&partial_sigs, &r_hat).unwrap();
+            assert!(signature.verify(&apk, &message, true).is_ok());
+            let s: FE = ECScalar::from(&signature.s);
+            let c: FE = ECScalar::from(&c);
+            assert_eq!(base_point * s, r_hat + apk * c);
+        }
+    }
+
+    #[test]
+    fn test_key_agg_hex_round_trip() {
+        let pks: Vec<GE> = (0..3).map(|_| KeyPair::create().public_key).collect();
+        let key_agg = KeyAgg::key_aggregation_n(&pks, 1).unwrap();
+        let hex = key_agg.to_hex();
+        assert_eq!(hex.len(), 132);
+        let decoded = KeyAgg::from_hex(&hex).unwrap();
+        assert_eq!(decoded.apk, key_agg.apk);
+        assert_eq!(decoded.hash, key_agg.hash);
+        assert_eq!(decoded.negated, key_agg.negated);
+
+        assert!(KeyAgg::from_hex(&hex[..hex.len() - 2]).is_err());
+        assert!(KeyAgg::from_hex(&format!("{}00", hex)).is_err());
+        assert!(KeyAgg::from_hex(&hex.replacen("0", "g", 1)).is_err());
+        // 0x04 is no valid prefix of a compressed point
+        assert!(KeyAgg::from_hex(&format!("04{}", &hex[2..])).is_err());
+    }
+
+    #[test]
+    fn test_message_hasher_chunked() {
+        let message: Vec<u8> = (0..1 << 20).map(|i| (i % 251) as u8).collect();
+        let mut one_shot = MessageHasher::new();
+        one_shot.update(&message);
+        let digest = one_shot.finalize();
+
+        let mut chunked = MessageHasher::new();
+        for chunk in message.chunks(4096 + 7) {
+            chunked.update(chunk);
+        }
+        assert_eq!(chunked.finalize(), digest);
+
+        let party1_key = KeyPair::create();
+        let party1_ephemeral_key = EphemeralKey::create();
+        let r = party1_ephemeral_key.keypair.public_key;
+        let c = EphemeralKey::hash_0_prehashed(&r, &party1_key.public_key, &digest, false);
+        assert_eq!(
+            c,
+            EphemeralKey::hash_0(&r, &party1_key.public_key, &digest, false)
+        );
+        let s = EphemeralKey::sign(&party1_ephemeral_key, &c, &party1_key, &BigInt::from(1));
+        let signature = EphemeralKey::add_signature_parts(s, &BigInt::from(0), &r).unwrap();
+        assert!(signature
+            .verify_prehashed(&party1_key.public_key, &digest, false)
+            .is_ok());
+        assert!(signature
+            .verify_prehashed(&party1_key.public_key, &[0u8; 32], false)
+            .is_err());
+    }
+
+    #[test]
+    fn test_public_key_validation() {
+        let point = KeyPair::create().public_key;
+        let x = point.x_coor().unwrap();
+        let y = point.y_coor().unwrap();
+        let public_key = PublicKey::from_coor(&x, &y).unwrap();
+        assert_eq!(GE::from(public_key), point);
+        assert_eq!(PublicKey::from_point(point).unwrap(), public_key);
+        let bytes = BigInt::to_vec(&point.bytes_compressed_to_big_int());
+        assert_eq!(PublicKey::from_bytes(&bytes).unwrap(), public_key);
+
+        // (x, y + 1) is not on the curve
+        assert!(PublicKey::from_coor(&x, &(y.clone() + BigInt::from(1))).is_err());
+        assert!(PublicKey::from_coor(&BigInt::from(0), &BigInt::from(0)).is_err());
+        assert!(PublicKey::from_bytes(&[2u8; 3]).is_err());
+    }
+
+    #[test]
+    fn test_private_key_validation() {
+        assert!(PrivateKey::from_big_int(&BigInt::from(0)).is_err());
+        assert!(PrivateKey::from_big_int(&FE::q()).is_err());
+        let private_key = PrivateKey::from_big_int(&BigInt::from(5)).unwrap();
+        let five: FE = ECScalar::from(&BigInt::from(5));
+        let base_point: GE = ECPoint::generator();
+        assert_eq!(GE::from(private_key.public_key()), base_point * five);
+
+        let keypair = KeyPair::from_private_key(private_key);
+        assert_eq!(keypair.public_key, base_point * five);
+        assert_eq!(keypair.expose_secret(), BigInt::from(5));
+    }
+
+    #[test]
+    fn test_debug_redacts_secrets() {
+        let party1_key = KeyPair::create();
+        let debug = format!("{:?}", party1_key);
+        assert!(debug.contains("REDACTED"));
+        assert!(!debug.contains(&party1_key.expose_secret().to_hex()));
+        assert!(!debug.contains(&party1_key.expose_secret().to_str_radix(10)));
+
+        let party1_ephemeral_key = EphemeralKey::create();
+        let debug = format!("{:?}", party1_ephemeral_key);
+        assert!(debug.contains("REDACTED"));
+        assert!(!debug.contains(&party1_ephemeral_key.keypair.expose_secret().to_hex()));
+        assert!(!debug.contains(&party1_ephemeral_key.blind_factor.to_hex()));
+
+        let private_key = PrivateKey::from_big_int(&BigInt::from(5)).unwrap();
+        assert_eq!(format!("{:?}", private_key), "PrivateKey(REDACTED)");
+    }
+
+    #[test]
+    fn test_adaptor_signature() {
+        let message: [u8; 4] = [79, 77, 69, 82];
+        let party1_key = KeyPair::create();
+        let adaptor_secret = KeyPair::create();
+        let t = adaptor_secret.expose_secret();
+        let t_point = adaptor_secret.public_key;
+
+        let adaptor_signature = adaptor::adaptor_sign(&party1_key, &t_point, &message);
+        assert!(adaptor::adaptor_verify(
+            &adaptor_signature,
+            &party1_key.public_key,
+            &message,
+            &t_point
+        )
+        .is_ok());
+        let other_point = KeyPair::create().public_key;
+        assert!(adaptor::adaptor_verify(
+            &adaptor_signature,
+            &party1_key.public_key,
+            &message,
+            &other_point
+        )
+        .is_err());
+
+        let signature = adaptor::adapt(&adaptor_signature, &t).unwrap();
+        assert!(signature
+            .verify(&party1_key.public_key, &message, false)
+            .is_ok());
+        assert_eq!(
+            adaptor::extract(&adaptor_signature, &signature, &t_point),
+            Ok(t)
+        );
+    }
+
+    #[test]
+    fn test_adaptor_rejects_malformed_signatures() {
+        let message: [u8; 4] = [79, 77, 69, 82];
+        let party1_key = KeyPair::create();
+        let adaptor_secret = KeyPair::create();
+        let t_point = adaptor_secret.public_key;
+
+        // R = -T puts R + T at infinity
+        let cancelling = adaptor::AdaptorSignature {
+            r: negate_point(&t_point),
+            s: BigInt::from(1),
+        };
+        assert_eq!(
+            adaptor::adaptor_verify(&cancelling, &party1_key.public_key, &message, &t_point),
+            Err(MuSigError::InvalidPoint)
+        );
+        assert_eq!(
+            adaptor::adapt(&cancelling, &adaptor_secret.expose_secret()),
+            Err(MuSigError::InvalidPoint)
+        );
+
+        // s' out of range
+        let mut adaptor_signature = adaptor::adaptor_sign(&party1_key, &t_point, &message);
+        adaptor_signature.s = adaptor_signature.s + curve_order();
+        assert_eq!(
+            adaptor::adaptor_verify(
+                &adaptor_signature,
+                &party1_key.public_key,
+                &message,
+                &t_point
+            ),
+            Err(MuSigError::InvalidScalar)
+        );
+        assert_eq!(
+            adaptor::adapt(&adaptor_signature, &adaptor_secret.expose_secret()),
+            Err(MuSigError::InvalidScalar)
+        );
+        adaptor_signature.s = BigInt::from(0);
+        assert_eq!(
+            adaptor::adaptor_verify(
+                &adaptor_signature,
+                &party1_key.public_key,
+                &message,
+                &t_point
+            ),
+            Err(MuSigError::InvalidScalar)
+        );
+        assert_eq!(
+            adaptor::adapt(&adaptor_signature, &adaptor_secret.expose_secret()),
+            Err(MuSigError::InvalidScalar)
+        );
+    }
+
+    #[test]
+    fn test_adaptor_rejects_zero_secret() {
+        let message: [u8; 4] = [79, 77, 69, 82];
+        let party1_key = KeyPair::create();
+        let t_point = KeyPair::create().public_key;
+        let adaptor_signature = adaptor::adaptor_sign(&party1_key, &t_point, &message);
+
+        assert_eq!(
+            adaptor::adapt(&adaptor_signature, &BigInt::from(0)),
+            Err(MuSigError::InvalidScalar)
+        );
+        assert_eq!(
+            adaptor::adapt(&adaptor_signature, &curve_order()),
+            Err(MuSigError::InvalidScalar)
+        );
+    }
+
+    #[test]
+    fn test_adaptor_extract_rejects_mismatched_signature() {
+        let message: [u8; 4] = [79, 77, 69, 82];
+        let party1_key = KeyPair::create();
+        let adaptor_secret = KeyPair::create();
+        let t_point = adaptor_secret.public_key;
+        let adaptor_signature = adaptor::adaptor_sign(&party1_key, &t_point, &message);
+        let signature =
+            adaptor::adapt(&adaptor_signature, &adaptor_secret.expose_secret()).unwrap();
+
+        // a signature over another nonce does not belong to this adaptor signature
+        let other_adaptor_signature = adaptor::adaptor_sign(&party1_key, &t_point, &message);
+        assert_eq!(
+            adaptor::extract(&other_adaptor_signature, &signature, &t_point),
+            Err(MuSigError::InvalidSignature)
+        );
+        let other_point = KeyPair::create().public_key;
+        assert_eq!(
+            adaptor::extract(&adaptor_signature, &signature, &other_point),
+            Err(MuSigError::InvalidSignature)
+        );
+
+        // s and s' out of range
+        let mut out_of_range = signature.clone();
+        out_of_range.s = out_of_range.s + curve_order();
+        assert_eq!(
+            adaptor::extract(&adaptor_signature, &out_of_range, &t_point),
+            Err(MuSigError::InvalidScalar)
+        );
+        let mut out_of_range = adaptor_signature.clone();
+        out_of_range.s = out_of_range.s + curve_order();
+        assert_eq!(
+            adaptor::extract(&out_of_range, &signature, &t_point),
+            Err(MuSigError::InvalidScalar)
+        );
+    }
+
+    #[test]
+    fn test_verify_commitment_wrong_blind_factor() {
+        let party1_ephemeral_key = EphemeralKey::create();
+        let r = party1_ephemeral_key.keypair.public_key;
+        let commitment = &party1_ephemeral_key.commitment;
+        assert!(EphemeralKey::verify_commitment(
+            &r,
+            &party1_ephemeral_key.blind_factor,
+            commitment
+        )
+        .is_ok());
+
+        let wrong_blind_factor = party1_ephemeral_key.blind_factor.clone() + BigInt::from(1);
+        let result = EphemeralKey::verify_commitment(&r, &wrong_blind_factor, commitment);
+        assert_eq!(
+            result,
+            Err(CommitmentError {
+                party: None,
+                commitment: commitment.clone(),
+            })
+        );
+        assert!(!EphemeralKey::test_com(&r, &wrong_blind_factor, commitment));
+    }
+
+    #[test]
+    fn test_tagged_and_untagged_challenges_differ() {
+        let keypair = KeyPair::create();
+        let r_x = KeyPair::create().public_key.x_coor().unwrap();
+        let apk = keypair.public_key.bytes_compressed_to_big_int();
+        let message = message_to_field_input(b"domain separation");
+
+        let tagged =
+            HSha256::create_hash(&[&BigInt::from(&b"musig/chal"[..]), &r_x, &apk, &message]);
+        let untagged = HSha256::create_hash(&[&BigInt::from(0), &r_x, &apk, &message]);
+        assert_ne!(tagged, untagged);
+
+        let challenge = compute_challenge(
+            &r_x,
+            &keypair.public_key,
+            b"domain separation",
+            ChallengeMode::Musig,
+        );
+        if cfg!(feature = "legacy_hashes") {
+            assert_eq!(challenge, untagged);
+        } else {
+            assert_eq!(challenge, tagged);
+        }
+        assert_ne!(domain_tag("musig/agg", 1), domain_tag("musig/nonce", 2));
+    }
+
+    #[test]
+    fn test_public_key_bytes() {
+        let keys: Vec<KeyPair> = (0..3).map(|_| KeyPair::create()).collect();
+        let pks: Vec<GE> = keys.iter().map(|key| key.public_key).collect();
+        let key_agg = KeyAgg::key_aggregation_n(&pks, 0).unwrap();
+
+        for bytes in keys
+            .iter()
+            .map(|key| key.public_key_bytes())
+            .chain(Some(key_agg.apk_bytes()))
+        {
+            assert_eq!(bytes.len(), 33);
+            assert!(bytes[0] == 0x02 || bytes[0] == 0x03);
+        }
+        assert_eq!(
+            PublicKey::from_bytes(&keys[0].public_key_bytes()).unwrap(),
+            PublicKey::from_point(keys[0].public_key).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_verify_strict_rejects_odd_nonce() {
+        let party1_key = KeyPair::create();
+        let message: [u8; 4] = [79, 77, 69, 82];
+        let mut ephemeral_keys = (0..).map(|_| EphemeralKey::create());
+        for &odd_y in &[false, true] {
+            let party1_ephemeral_key = ephemeral_keys
+                .by_ref()
+                .find(|key| bip340::has_even_y(&key.keypair.public_key) != odd_y)
+                .unwrap();
+            let r = party1_ephemeral_key.keypair.public_key;
+            let c = EphemeralKey::hash_0(&r, &party1_key.public_key, &message, false);
+            let s = EphemeralKey::sign(&party1_ephemeral_key, &c, &party1_key, &BigInt::from(1));
+            let signature = EphemeralKey::add_signature_parts(s, &BigInt::from(0), &r).unwrap();
+
+            // the x coordinate is the same for both parities of R
+            assert!(signature
+                .verify(&party1_key.public_key, &message, false)
+                .is_ok());
+            assert_eq!(
+                signature
+                    .verify_strict(&party1_key.public_key, &message, false)
+                    .is_ok(),
+                !odd_y
+            );
+        }
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_parallel_key_aggregation_matches_serial() {
+        extern crate rayon;
+
+        let mut builder = KeyAggBuilder::new();
+        for _ in 0..256 {
+            builder.add_key(&KeyPair::create().public_key);
+        }
+        let (serial_apk, serial_hashes, serial_negated) =
+            KeyAggBuilder::sum_weighted_keys(builder.weighted_keys::<HSha256>());
+        for &num_threads in &[1, 2, 7, 16] {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(num_threads)
+                .build()
+                .unwrap();
+            let (apk, hashes, negated) = pool.install(|| {
+                KeyAggBuilder::sum_weighted_keys(builder.weighted_keys_parallel::<HSha256>())
+            });
+            assert_eq!(apk, serial_apk);
+            assert_eq!(hashes, serial_hashes);
+            assert_eq!(negated, serial_negated);
+        }
+        assert_eq!(builder.finalize(0).apk, serial_apk);
+    }
+
+    #[test]
+    fn test_musig_error_variants() {
+        let keypair = KeyPair::create();
+        let pk = keypair.public_key;
+        let message: [u8; 4] = [79, 77, 69, 82];
+        let signature = sign_one_party(&keypair, &message);
+
+        assert_eq!(
+            signature.verify(&pk, &[0u8], false),
+            Err(MuSigError::InvalidSignature)
+        );
+        assert_eq!(
+            signature.verify_strict(&KeyPair::create().public_key, &message, false),
+            Err(MuSigError::InvalidSignature)
+        );
+        let mut bytes = signature.to_bytes();
+        for byte in bytes[32..].iter_mut() {
+            *byte = 0xff;
+        }
+        assert_eq!(
+            Signature::from_bytes(&bytes),
+            Err(MuSigError::InvalidScalar)
+        );
+        assert_eq!(
+            KeyPair::create_from_private_key(&FE::q()).err(),
+            Some(MuSigError::InvalidScalar)
+        );
+        assert_eq!(
+            PublicKey::from_bytes(&[2u8; 32]),
+            Err(MuSigError::InvalidEncoding)
+        );
+        assert_eq!(
+            PublicKey::from_coor(&BigInt::from(1), &BigInt::from(1)),
+            Err(MuSigError::InvalidPoint)
+        );
+        assert_eq!(
+            KeyAgg::from_hex("00").err(),
+            Some(MuSigError::InvalidEncoding)
+        );
+        assert_eq!(
+            EphemeralKey::aggregate_ephemeral_pub_keys(&[]),
+            Err(MuSigError::EmptyKeySet)
+        );
+        assert_eq!(
+            EphemeralKey::aggregate_signature_parts(&[], &pk),
+            Err(MuSigError::EmptyKeySet)
+        );
+
+        let ephemeral_key = EphemeralKey::create();
+        let commitment_error = EphemeralKey::verify_commitment(
+            &ephemeral_key.keypair.public_key,
+            &(ephemeral_key.blind_factor.clone() + BigInt::from(1)),
+            &ephemeral_key.commitment,
+        )
+        .unwrap_err();
+        assert_eq!(
+            MuSigError::from(commitment_error),
+            MuSigError::CommitmentMismatch { index: None }
+        );
+    }
+
+    #[test]
+    fn test_verifier_matches_verify() {
+        let verifier = Verifier::new();
+        let base_point: GE = ECPoint::generator();
+        let q_minus_one = FE::q() - BigInt::from(1);
+        for s in &[
+            BigInt::from(1),
+            BigInt::from(16),
+            BigInt::from(0xf0f0),
+            q_minus_one,
+        ] {
+            let s_fe: FE = ECScalar::from(s);
+            assert_eq!(verifier.base_mul(&s_fe), base_point * s_fe);
+        }
+
+        let message: [u8; 4] = [79, 77, 69, 82];
+        for _ in 0..5 {
+            let keypair = KeyPair::create();
+            let signature = sign_one_party(&keypair, &message);
+            assert!(verifier
+                .verify(&signature, &keypair.public_key, &message, false)
+                .is_ok());
+            let other_pk = KeyPair::create().public_key;
+            assert_eq!(
+                verifier.verify(&signature, &other_pk, &message, false),
+                signature.verify(&other_pk, &message, false)
+            );
+        }
+    }
+
+    #[test]
+    fn test_sign_prehashed_digest() {
+        let message = b"spend output 0";
+        let mut hasher = MessageHasher::new();
+        hasher.update(message);
+        let digest = hasher.finalize();
+
+        let party1_key = KeyPair::create();
+        let party1_ephemeral_key = EphemeralKey::create_deterministic_prehashed(
+            &party1_key,
+            &party1_key.public_key,
+            &digest,
+            None,
+        );
+        let r = party1_ephemeral_key.keypair.public_key;
+        let c = EphemeralKey::hash_0_prehashed(&r, &party1_key.public_key, &digest, true);
+        let s = EphemeralKey::sign(&party1_ephemeral_key, &c, &party1_key, &BigInt::from(1));
+        let signature = EphemeralKey::add_signature_parts(s, &BigInt::from(0), &r).unwrap();
+
+        assert!(signature
+            .verify_prehashed(&party1_key.public_key, &digest, true)
+            .is_ok());
+        assert!(Verifier::new()
+            .verify_prehashed(&signature, &party1_key.public_key, &digest, true)
+            .is_ok());
+        // the digest is the message, verifying against the original message fails
+        assert!(signature
+            .verify(&party1_key.public_key, message, true)
+            .is_err());
+    }
+
+    #[test]
+    fn test_add_signature_parts_full() {
+        let message: [u8; 4] = [79, 77, 69, 82];
+        let (_, key_aggs, ephemeral_keys, c, partial_sigs) =
+            n_party_partial_signatures(2, &message);
+        let r_tag = EphemeralKey::add_ephemeral_pub_keys(
+            &ephemeral_keys[0].keypair.public_key,
+            &ephemeral_keys[1].keypair.public_key,
+        );
+        let (r, s) = EphemeralKey::add_signature_parts_full(
+            partial_sigs[0].clone(),
+            &partial_sigs[1],
+            &r_tag,
+        )
+        .unwrap();
+        let signature =
+            EphemeralKey::add_signature_parts(partial_sigs[0].clone(), &partial_sigs[1], &r_tag)
+                .unwrap();
+
+        assert_eq!(r, r_tag);
+        assert_eq!(r.x_coor().unwrap(), signature.r_x);
+        assert_eq!(s, signature.s);
+        assert_eq!(
+            EphemeralKey::hash_0(&r, &key_aggs[0].apk, &message, true),
+            c
+        );
+        assert!(signature.verify(&key_aggs[0].apk, &message, true).is_ok());
+    }
+
+    #[test]
+    fn test_key_aggregation_n_bounds() {
+        let pks: Vec<GE> = (0..3).map(|_| KeyPair::create().public_key).collect();
+        assert_eq!(
+            KeyAgg::key_aggregation_n(&[], 0).err(),
+            Some(MuSigError::EmptyKeySet)
+        );
+        assert_eq!(
+            KeyAgg::key_aggregation_n(&pks, 3).err(),
+            Some(MuSigError::IndexOutOfRange)
+        );
+        assert_eq!(
+            KeyAgg::key_aggregation_n(&pks, usize::max_value()).err(),
+            Some(MuSigError::IndexOutOfRange)
+        );
+        assert!(KeyAgg::key_aggregation_n(&pks, 2).is_ok());
+    }
+
+    #[test]
+    fn test_blind_signature() {
+        let signer_key = KeyPair::create();
+        let message = b"blinded message";
+
+        let signer_session = blind::SignerSession::create();
+        let r = signer_session.public_nonce();
+        let user_session = blind::UserSession::new(&signer_key.public_key, &r, message);
+        let blinded_challenge = user_session.blinded_challenge();
+
+        // the signer only sees R and the blinded challenge, which is neither the challenge over
+        // R nor tied to the message: a second session with the same R and message gives a
+        // different blinded challenge
+        assert_ne!(
+            blinded_challenge,
+            EphemeralKey::hash_0(&r, &signer_key.public_key, message, false)
+        );
+        let other_session = blind::UserSession::new(&signer_key.public_key, &r, message);
+        assert_ne!(other_session.blinded_challenge(), blinded_challenge);
+
+        let s = signer_session.sign(&signer_key, &blinded_challenge);
+        let signature = user_session.unblind(&s).unwrap();
+        assert!(signature
+            .verify(&signer_key.public_key, message, false)
+            .is_ok());
+        assert!(signature
+            .verify(&signer_key.public_key, b"other message", false)
+            .is_err());
+        assert_ne!(s, signature.s);
+
+        // an answer to a different challenge does not unblind
+        assert_eq!(
+            other_session.unblind(&s).err(),
+            Some(MuSigError::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn test_challenge_mode_matches_musig_bit() {
+        let message: [u8; 4] = [79, 77, 69, 82];
+        let apk = KeyPair::create().public_key;
+        let r = KeyPair::create().public_key;
+        assert_eq!(
+            EphemeralKey::hash_0(&r, &apk, &message, ChallengeMode::Musig),
+            EphemeralKey::hash_0(&r, &apk, &message, true)
+        );
+        assert_eq!(
+            EphemeralKey::hash_0(&r, &apk, &message, ChallengeMode::Plain),
+            EphemeralKey::hash_0(&r, &apk, &message, false)
+        );
+        assert_ne!(
+            EphemeralKey::hash_0(&r, &apk, &message, ChallengeMode::Musig),
+            EphemeralKey::hash_0(&r, &apk, &message, ChallengeMode::Plain)
+        );
+
+        let keypair = KeyPair::create();
+        let signature = sign_one_party(&keypair, &message);
+        assert!(signature
+            .verify(&keypair.public_key, &message, ChallengeMode::Plain)
+            .is_ok());
+        assert!(signature
+            .verify(&keypair.public_key, &message, ChallengeMode::Musig)
+            .is_err());
+    }
+
+    #[test]
+    fn test_interoperability_vectors() {
+        for vector in TEST_VECTORS {
+            if cfg!(feature = "legacy_hashes")
+                || cfg!(feature = "prefixed_msg")
+                || cfg!(feature = "fixed_msg_hash")
+                || (cfg!(feature = "musig_spec_v1") && vector.mode == ChallengeMode::Musig)
+            {
+                continue;
+            }
+            assert!(test_vectors::verify_vector(vector).is_ok());
+
+            let keys: Vec<KeyPair> = test_vectors::private_keys(vector)
+                .iter()
+                .map(|private_key| KeyPair::create_from_private_key(private_key).unwrap())
+                .collect();
+            let pks: Vec<GE> = keys.iter().map(|key| key.public_key).collect();
+            let public_key = match vector.mode {
+                ChallengeMode::Plain => pks[0],
+                ChallengeMode::Musig | ChallengeMode::MusigLegacy => {
+                    KeyAgg::aggregate_only(&pks).unwrap()
+                }
+            };
+            assert_eq!(
+                BigInt::to_vec(&public_key.bytes_compressed_to_big_int()),
+                hex::decode(vector.public_key).unwrap()
+            );
+
+            // a flipped bit of s is rejected
+            let mut bytes = [0u8; 64];
+            bytes.copy_from_slice(&hex::decode(vector.signature).unwrap());
+            bytes[63] ^= 1;
+            let tampered = Signature::from_bytes(&bytes).unwrap();
+            let message = hex::decode(vector.message).unwrap();
+            assert!(tampered.verify(&public_key, &message, vector.mode).is_err());
+        }
+    }
+
+    // a single signer signature with an even y nonce
+    fn sign_one_party_even_nonce(party1_key: &KeyPair, message: &[u8]) -> Signature {
+        let mut party1_ephemeral_key = EphemeralKey::create();
+        let r = party1_ephemeral_key.keypair.public_key;
+        let (r_hat, negated) = EphemeralKey::aggregate_ephemeral_pub_keys(&[r]).unwrap();
+        if negated {
+            party1_ephemeral_key.negate();
+        }
+        let c = EphemeralKey::hash_0(
+            &r_hat,
+            &party1_key.public_key,
+            message,
+            ChallengeMode::Plain,
+        );
+        let s = EphemeralKey::sign(&party1_ephemeral_key, &c, party1_key, &BigInt::from(1));
+        EphemeralKey::add_signature_parts(s, &BigInt::from(0), &r_hat).unwrap()
+    }
+
+    #[test]
+    fn test_half_aggregation() {
+        let message = b"same message";
+        let keys: Vec<KeyPair> = (0..5).map(|_| KeyPair::create()).collect();
+        let mut pks: Vec<GE> = keys.iter().map(|key| key.public_key).collect();
+        let sigs: Vec<Signature> = keys
+            .iter()
+            .map(|key| sign_one_party_even_nonce(key, message))
+            .collect();
+        for (sig, pk) in sigs.iter().zip(&pks) {
+            assert!(sig.verify_strict(pk, message, ChallengeMode::Plain).is_ok());
+        }
+
+        let agg = half_agg::half_aggregate(&sigs, &pks, message).unwrap();
+        assert_eq!(agg.r_xs.len(), 5);
+        assert!(half_agg::half_agg_verify(&agg, &pks, message, ChallengeMode::Plain).is_ok());
+        assert!(
+            half_agg::half_agg_verify(&agg, &pks, b"other message", ChallengeMode::Plain).is_err()
+        );
+
+        // one signer's key swapped
+        pks[2] = KeyPair::create().public_key;
+        assert_eq!(
+            half_agg::half_agg_verify(&agg, &pks, message, ChallengeMode::Plain),
+            Err(MuSigError::InvalidSignature)
+        );
+        assert_eq!(
+            half_agg::half_agg_verify(&agg, &pks[..4], message, ChallengeMode::Plain),
+            Err(MuSigError::IndexOutOfRange)
+        );
+        assert_eq!(
+            half_agg::half_aggregate(&[], &[], message),
+            Err(MuSigError::EmptyKeySet)
+        );
+        assert_eq!(
+            half_agg::half_aggregate(&sigs, &pks[..4], message),
+            Err(MuSigError::IndexOutOfRange)
+        );
+    }
+
+    // the coefficients cover the keys and the message, so an aggregate made for one key list
+    // or message does not verify for another even where the signatures themselves would
+    #[test]
+    fn test_half_aggregation_binds_keys_and_message() {
+        let message = b"same message";
+        let keys: Vec<KeyPair> = (0..3).map(|_| KeyPair::create()).collect();
+        let pks: Vec<GE> = keys.iter().map(|key| key.public_key).collect();
+        let sigs: Vec<Signature> = keys
+            .iter()
+            .map(|key| sign_one_party_even_nonce(key, message))
+            .collect();
+        let agg = half_agg::half_aggregate(&sigs, &pks, message).unwrap();
+
+        // aggregating under another message gives another s
+        let other = half_agg::half_aggregate(&sigs, &pks, b"other message").unwrap();
+        assert_eq!(other.r_xs, agg.r_xs);
+        assert_ne!(other.s, agg.s);
+        assert!(half_agg::half_agg_verify(&other, &pks, message, ChallengeMode::Plain).is_err());
+
+        // swapping one key changes every coefficient, not only the swapped term
+        let mut swapped = pks.clone();
+        swapped[1] = KeyPair::create().public_key;
+        let rogue = half_agg::half_aggregate(&sigs, &swapped, message).unwrap();
+        assert_ne!(rogue.s, agg.s);
+        assert_eq!(
+            half_agg::half_agg_verify(&agg, &swapped, message, ChallengeMode::Plain),
+            Err(MuSigError::InvalidSignature)
+        );
+        assert_eq!(
+            half_agg::half_agg_verify(&rogue, &swapped, message, ChallengeMode::Plain),
+            Err(MuSigError::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn test_add_signature_parts_rejects_unreduced_share() {
+        let r = KeyPair::create().public_key;
+        let q_plus_5 = FE::q() + BigInt::from(5);
+        assert_eq!(
+            EphemeralKey::add_signature_parts(q_plus_5.clone(), &BigInt::from(1), &r),
+            Err(MuSigError::InvalidScalar)
+        );
+        assert_eq!(
+            EphemeralKey::add_signature_parts(BigInt::from(1), &q_plus_5, &r),
+            Err(MuSigError::InvalidScalar)
+        );
+        assert_eq!(
+            EphemeralKey::add_signature_parts(BigInt::from(-1), &BigInt::from(0), &r),
+            Err(MuSigError::InvalidScalar)
+        );
+        assert_eq!(
+            EphemeralKey::aggregate_signature_parts(&[BigInt::from(1), q_plus_5], &r),
+            Err(MuSigError::InvalidScalar)
+        );
+        assert_eq!(
+            EphemeralKey::add_signature_parts(BigInt::from(1), &BigInt::from(5), &r)
+                .unwrap()
+                .s,
+            BigInt::from(6)
+        );
+    }
+
+    #[test]
+    fn test_key_agg_coefficient() {
+        let coefficient =
+            |pks: &[GE], i| -> FE { ECScalar::from(&KeyAgg::coefficient(pks, i).unwrap()) };
+        let mut pks: Vec<GE> = (0..4).map(|_| KeyPair::create().public_key).collect();
+        let apk = KeyAgg::aggregate_only(&pks).unwrap();
+        pks.sort_by_key(|pk| pk.bytes_compressed_to_big_int());
+        let sum = (1..pks.len()).fold(pks[0] * coefficient(&pks, 0), |acc, i| {
+            acc + pks[i] * coefficient(&pks, i)
+        });
+        assert_eq!(sum, apk);
+        assert_eq!(
+            KeyAgg::coefficient(&pks, 2).unwrap(),
+            KeyAgg::key_aggregation_n(&pks, 2).unwrap().hash
+        );
+        assert_eq!(
+            KeyAgg::coefficient(&pks, 4),
+            Err(MuSigError::IndexOutOfRange)
+        );
+    }
+
+    // key generation, both commitment rounds and the partial signatures of a two party session
+    fn run_musig_session(message: &[u8]) -> Signature {
+        let party1_key = KeyPair::create();
+        let party2_key = KeyPair::create();
+        let party1_pk = party1_key.public_key;
+        let party2_pk = party2_key.public_key;
+        let party1_session = MuSigSession::new(party1_key, &party2_pk, message);
+        let party2_session = MuSigSession::new(party2_key, &party1_pk, message);
+
+        let party1_commitment = party1_session.first_message();
+        let party2_commitment = party2_session.first_message();
+        let party1_session = party1_session.receive_commitment(&party2_commitment);
+        let party2_session = party2_session.receive_commitment(&party1_commitment);
+        let party2_reveal = party2_session.second_message();
+        let party1_reveal = party1_session.second_message();
+        let party1_session = party1_session.receive_reveal(&party2_reveal).unwrap();
+        let party2_session = party2_session.receive_reveal(&party1_reveal).unwrap();
+        let party2_s = party2_session.partial_signature();
+        party1_session.finalize(&party2_s).unwrap()
+    }
+
+    #[test]
+    fn test_seeded_rng_reproduces_protocol_run() {
+        let message: [u8; 4] = [79, 77, 69, 82];
+        let first = with_seeded_rng([7u8; 32], || run_musig_session(&message));
+        let second = with_seeded_rng([7u8; 32], || run_musig_session(&message));
+        assert_eq!(first.to_bytes()[..], second.to_bytes()[..]);
+
+        let other_seed = with_seeded_rng([8u8; 32], || run_musig_session(&message));
+        assert_ne!(first, other_seed);
+        // outside of with_seeded_rng the OS rng is used again
+        assert_ne!(first, run_musig_session(&message));
+    }
+
+    #[test]
+    fn test_verify_commitments_reports_bad_opening() {
+        let ephemeral_keys: Vec<EphemeralKey> = (0..5).map(|_| EphemeralKey::create()).collect();
+        let comms: Vec<BigInt> = ephemeral_keys
+            .iter()
+            .map(|key| key.commitment.clone())
+            .collect();
+        let mut reveals: Vec<(GE, BigInt)> = ephemeral_keys
+            .iter()
+            .map(|key| (key.keypair.public_key, key.blind_factor.clone()))
+            .collect();
+        assert!(EphemeralKey::verify_commitments(&reveals, &comms).is_ok());
+
+        reveals[3].0 = KeyPair::create().public_key;
+        assert_eq!(
+            EphemeralKey::verify_commitments(&reveals, &comms),
+            Err(MuSigError::CommitmentMismatch { index: Some(3) })
+        );
+        assert_eq!(
+            EphemeralKey::verify_commitments(&reveals[..4], &comms),
+            Err(MuSigError::IndexOutOfRange)
+        );
+    }
+
+    #[test]
+    fn test_sign_in_context_matches_sign() {
+        let message: [u8; 4] = [79, 77, 69, 82];
+        let party1_key = KeyPair::create();
+        let party2_key = KeyPair::create();
+        let party1_ephemeral_key = EphemeralKey::create();
+        let party2_ephemeral_key = EphemeralKey::create();
+        let key_agg = KeyAgg::key_aggregation(&party1_key.public_key, &party2_key.public_key);
+        let r_tag = EphemeralKey::add_ephemeral_pub_keys(
+            &party1_ephemeral_key.keypair.public_key,
+            &party2_ephemeral_key.keypair.public_key,
+        );
+
+        let ctx = SigningContext::new(&r_tag, &key_agg, &message, ChallengeMode::Musig);
+        let c = EphemeralKey::hash_0(&r_tag, &key_agg.apk, &message, ChallengeMode::Musig);
+        assert_eq!(ctx.challenge, c);
+        assert_eq!(ctx.coefficient, key_agg.hash);
+        assert_eq!(
+            party1_ephemeral_key.sign_in_context(&ctx, &party1_key),
+            EphemeralKey::sign(&party1_ephemeral_key, &c, &party1_key, &key_agg.hash)
+        );
+    }
+
+    #[test]
+    fn test_pk_sk_round_trip() {
+        let keypair = KeyPair::create();
+        let restored = KeyPair::from_sk(keypair.to_sk()).unwrap();
+        assert_eq!(restored.public_key, keypair.public_key);
+        assert_eq!(restored.expose_secret(), keypair.expose_secret());
+        assert_eq!(restored.to_pk(), keypair.to_pk());
+
+        let public_key = PublicKey::from_pk(&keypair.to_pk()).unwrap();
+        assert_eq!(*public_key.as_point(), keypair.public_key);
+        assert_eq!(public_key.to_pk(), keypair.to_pk());
+    }
+
+    #[test]
+    fn test_key_aggregation_coefficients() {
+        let pks: Vec<GE> = (0..4).map(|_| KeyPair::create().public_key).collect();
+        let mut encoded_pks: Vec<BigInt> = pks
+            .iter()
+            .map(|pk| pk.bytes_compressed_to_big_int())
+            .collect();
+        encoded_pks.sort();
+        let tag = domain_tag("musig/agg", 1);
+        let list_tag = BigInt::from(&b"musig/keylist"[..]);
+        let mut list_vec = vec![&list_tag];
+        list_vec.extend(&encoded_pks);
+        let key_list_commitment = HSha256::create_hash(&list_vec);
+
+        let key_agg = KeyAgg::key_aggregation_n(&pks, 0).unwrap();
+        let mut apk: Option<GE> = None;
+        for (i, encoded_pk) in encoded_pks.iter().enumerate() {
+            let mut vec = vec![&tag];
+            if cfg!(feature = "musig_spec_v1") {
+                vec.push(&key_list_commitment);
+                vec.push(encoded_pk);
+            } else {
+                vec.push(encoded_pk);
+                vec.extend(&encoded_pks);
+            }
+            let a: FE = ECScalar::from(&HSha256::create_hash(&vec));
+            let pk = pks
+                .iter()
+                .find(|pk| pk.bytes_compressed_to_big_int() == *encoded_pk)
+                .unwrap();
+            let weighted_pk = *pk * a;
+            apk = Some(match apk {
+                Some(apk) => apk + weighted_pk,
+                None => weighted_pk,
+            });
+            let expected = if key_agg.negated {
+                negate_scalar(&a).to_big_int()
+            } else {
+                a.to_big_int()
+            };
+            assert_eq!(KeyAgg::coefficient(&pks, i).unwrap(), expected);
+        }
+        let apk = apk.unwrap();
+        let apk = if key_agg.negated {
+            negate_point(&apk)
+        } else {
+            apk
+        };
+        assert_eq!(key_agg.apk, apk);
+    }
+
+    #[test]
+    fn test_signature_is_valid_encoding() {
+        let party1_key = KeyPair::create();
+        let signature = sign_one_party(&party1_key, b"message");
+        let bytes = signature.to_bytes();
+        assert!(Signature::is_valid_encoding(&bytes));
+
+        // s = q is out of range
+        let mut out_of_range = bytes;
+        out_of_range[32..].copy_from_slice(&BigInt::to_vec(&FE::q()));
+        assert!(!Signature::is_valid_encoding(&out_of_range));
+        assert!(Signature::from_bytes(&out_of_range).is_err());
+
+        assert!(!Signature::is_valid_encoding(&bytes[..63]));
+        let mut too_long = bytes.to_vec();
+        too_long.push(0);
+        assert!(!Signature::is_valid_encoding(&too_long));
+        assert!(!Signature::is_valid_encoding(&[]));
+    }
+
+    #[test]
+    fn test_key_aggregation_with_pops() {
+        let keys: Vec<KeyPair> = (0..3).map(|_| KeyPair::create()).collect();
+        let pks: Vec<GE> = keys.iter().map(|key| key.public_key).collect();
+        let mut pops: Vec<_> = keys.iter().map(|key| key.prove_possession()).collect();
+        for (pk, pop) in pks.iter().zip(&pops) {
+            assert!(verify_possession(pk, pop).is_ok());
+        }
+        let key_agg = KeyAgg::key_aggregation_with_pops(&pks, &pops, 1).unwrap();
+        assert_eq!(key_agg.apk, KeyAgg::key_aggregation_n(&pks, 1).unwrap().apk);
+
+        // a proof made by another key is rejected, as is an ordinary signature
+        pops[2] = KeyPair::create().prove_possession();
+        assert_eq!(
+            KeyAgg::key_aggregation_with_pops(&pks, &pops, 1).unwrap_err(),
+            MuSigError::InvalidSignature
+        );
+        pops[2].signature = sign_one_party(&keys[2], b"message");
+        assert!(KeyAgg::key_aggregation_with_pops(&pks, &pops, 1).is_err());
+        assert_eq!(
+            KeyAgg::key_aggregation_with_pops(&pks, &pops[..2], 1).unwrap_err(),
+            MuSigError::IndexOutOfRange
+        );
+    }
+
+    #[test]
+    fn test_key_aggregation_iter() {
+        let pks = [
+            KeyPair::create().public_key,
+            KeyPair::create().public_key,
+            KeyPair::create().public_key,
+        ];
+        let from_slice = KeyAgg::key_aggregation_n(&pks[..], 2).unwrap();
+        let map: HashMap<usize, GE> = pks.iter().cloned().enumerate().collect();
+        let from_iter = KeyAgg::key_aggregation_iter(map.values().cloned(), 2).unwrap();
+        assert_eq!(from_iter.apk, from_slice.apk);
+        assert_eq!(from_iter.hash, from_slice.hash);
+        assert_eq!(from_iter.negated, from_slice.negated);
+
+        assert_eq!(
+            KeyAgg::key_aggregation_iter(Vec::new(), 0).unwrap_err(),
+            MuSigError::EmptyKeySet
+        );
+        assert_eq!(
+            KeyAgg::key_aggregation_iter(pks.iter().cloned(), 3).unwrap_err(),
+            MuSigError::IndexOutOfRange
+        );
+    }
+
+    #[test]
+    fn test_curve_order() {
+        assert_eq!(curve_order(), FE::q());
+        let minus_one: FE = ECScalar::from(&(curve_order() - BigInt::from(1)));
+        let one: FE = ECScalar::from(&BigInt::from(1));
+        assert_eq!((minus_one + one).to_big_int(), BigInt::from(0));
+    }
+
+    #[test]
+    fn test_session_messages_serialization() {
+        let party1_key = KeyPair::create();
+        let party2_key = KeyPair::create();
+        let party1_session = MuSigSession::new(party1_key, &party2_key.public_key, b"message");
+
+        let round1 = party1_session.first_message();
+        let json = serde_json::to_string(&round1).unwrap();
+        let decoded: Round1Message = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, round1);
+
+        let session = party1_session.receive_commitment(&decoded);
+        let round2 = session.second_message();
+        let json = serde_json::to_string(&round2).unwrap();
+        let decoded: Round2Message = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, round2);
+    }
+
+    #[test]
+    fn test_small_order_points_rejected() {
+        // secp256k1 has cofactor 1, the only point outside the prime order group is the point
+        // at infinity, R + (-R)
+        let r = KeyPair::create().public_key;
+        assert_eq!(
+            EphemeralKey::aggregate_ephemeral_pub_keys(&[r, negate_point(&r)]),
+            Err(MuSigError::InvalidPoint)
+        );
+    }
+
+    #[test]
+    fn test_key_agg_members() {
+        let pks: Vec<GE> = (0..4).map(|_| KeyPair::create().public_key).collect();
+        let key_agg_members = KeyAgg::with_members(&pks, 1).unwrap();
+        let key_agg = KeyAgg::key_aggregation_n(&pks, 1).unwrap();
+        assert_eq!(key_agg_members.key_agg.apk, key_agg.apk);
+        assert_eq!(key_agg_members.key_agg.hash, key_agg.hash);
+        for pk in &pks {
+            assert!(key_agg_members.contains(pk));
+        }
+        assert!(!key_agg_members.contains(&KeyPair::create().public_key));
+
+        let members = key_agg_members.members();
+        assert_eq!(members.len(), pks.len());
+        for (i, pk) in members.iter().enumerate() {
+            assert_eq!(KeyAgg::sorted_position(&pks, pk), Some(i));
+        }
+    }
+
+    #[test]
+    fn test_verify_low_s() {
+        let message = b"message";
+        let party1_key = KeyPair::create();
+        let mut signatures: Vec<Signature> = (0..32)
+            .map(|_| sign_one_party(&party1_key, message))
+            .collect();
+        signatures.sort_by_key(|signature| signature.is_low_s());
+        let high_s = signatures.first().unwrap();
+        let low_s = signatures.last().unwrap();
+        assert!(!high_s.is_low_s() && low_s.is_low_s());
+
+        assert!(high_s
+            .verify(&party1_key.public_key, message, false)
+            .is_ok());
+        assert_eq!(
+            high_s.verify_low_s(&party1_key.public_key, message, false),
+            Err(MuSigError::InvalidScalar)
+        );
+        assert!(low_s
+            .verify_low_s(&party1_key.public_key, message, false)
+            .is_ok());
+
+        // q - s does not verify
+        let flipped = Signature {
+            s: curve_order() - &high_s.s,
+            r_x: high_s.r_x.clone(),
+        };
+        assert!(flipped
+            .verify(&party1_key.public_key, message, false)
+            .is_err());
+    }
+
+    // the default encoding is ambiguous, it drops the leading zero
+    #[cfg(not(any(feature = "prefixed_msg", feature = "fixed_msg_hash")))]
+    #[test]
+    fn test_message_to_field_input_drops_leading_zeros() {
+        let message = b"message".to_vec();
+        let mut leading_zero = vec![0u8];
+        leading_zero.extend_from_slice(&message);
+        assert_eq!(message_to_field_input(&message), BigInt::from(&message[..]));
+        assert_eq!(
+            message_to_field_input(&message),
+            message_to_field_input(&leading_zero)
+        );
+        assert_eq!(message_to_field_input(&[]), message_to_field_input(&[0]));
+    }
+
+    #[cfg(all(feature = "prefixed_msg", not(feature = "fixed_msg_hash")))]
+    #[test]
+    fn test_message_to_field_input_prefixed() {
+        let message = b"message".to_vec();
+        let mut leading_zero = vec![0u8];
+        leading_zero.extend_from_slice(&message);
+        assert_eq!(
+            BigInt::to_vec(&message_to_field_input(&message))[..],
+            [&[1u8][..], &message[..]].concat()[..]
+        );
+        assert_ne!(
+            message_to_field_input(&message),
+            message_to_field_input(&leading_zero)
+        );
+        assert_ne!(message_to_field_input(&[]), message_to_field_input(&[0]));
+
+        let r = KeyPair::create().public_key;
+        let apk = KeyPair::create().public_key;
+        for mode in [ChallengeMode::Plain, ChallengeMode::Musig].iter() {
+            assert_ne!(
+                EphemeralKey::hash_0(&r, &apk, &message, *mode),
+                EphemeralKey::hash_0(&r, &apk, &leading_zero, *mode)
+            );
+        }
+    }
+
+    #[cfg(feature = "fixed_msg_hash")]
+    #[test]
+    fn test_message_to_field_input_fixed_hash() {
+        use protocols::aggsig::to_fixed_bytes;
+        use sha2::{Digest, Sha256};
+
+        let message = b"message".to_vec();
+        let mut leading_zero = vec![0u8];
+        leading_zero.extend_from_slice(&message);
+        assert_eq!(
+            to_fixed_bytes(&message_to_field_input(&message), 32),
+            Sha256::digest(&message).to_vec()
+        );
+        assert_ne!(
+            message_to_field_input(&message),
+            message_to_field_input(&leading_zero)
+        );
+    }
+
+    #[test]
+    fn test_sign_single() {
+        let keypair = KeyPair::create();
+        let message = b"single signer";
+        let signature = keypair.sign_single(message);
+        assert!(signature
+            .verify(&keypair.public_key, message, false)
+            .is_ok());
+        assert!(signature
+            .verify(&keypair.public_key, b"other message", false)
+            .is_err());
+        assert!(signature
+            .verify(&KeyPair::create().public_key, message, false)
+            .is_err());
+    }
+
+    #[test]
+    fn test_session_resumed_from_persisted_state() {
+        let message: [u8; 4] = [79, 77, 69, 82];
+        let party1_key = KeyPair::create();
+        let party2_key = KeyPair::create();
+        let party1_pk = party1_key.public_key;
+        let party2_pk = party2_key.public_key;
+        let party1_session = MuSigSession::new(party1_key, &party2_pk, &message);
+        let party2_session = MuSigSession::new(party2_key, &party1_pk, &message);
+
+        let party1_commitment = party1_session.first_message();
+        let party2_commitment = party2_session.first_message();
+        let party1_session = party1_session.receive_commitment(&party2_commitment);
+        let party2_session = party2_session.receive_commitment(&party1_commitment);
+        let party1_reveal = party1_session.second_message();
+        let party2_reveal = party2_session.second_message();
+
+        // party1 is interrupted after round one and restarts from the stored snapshot
+        let json = serde_json::to_string(&party1_session.persist()).unwrap();
+        let state: SessionState = serde_json::from_str(&json).unwrap();
+        assert_eq!(state.round(), 2);
+        let party1_session = MuSigSession::<Round2>::from_persisted(state).unwrap();
+        assert_eq!(party1_session.second_message(), party1_reveal);
+
+        let party1_session = party1_session.receive_reveal(&party2_reveal).unwrap();
+        let party2_session = party2_session.receive_reveal(&party1_reveal).unwrap();
+        let party1_s = party1_session.partial_signature();
+        let party2_s = party2_session.partial_signature();
+        let apk = party1_session.apk();
+        let party1_signature = party1_session.finalize(&party2_s).unwrap();
+        let party2_signature = party2_session.finalize(&party1_s).unwrap();
+        assert_eq!(party1_signature, party2_signature);
+        assert!(party1_signature.verify(&apk, &message, true).is_ok());
+    }
+
+    #[test]
+    fn test_persisted_state_is_validated() {
+        let party2_pk = KeyPair::create().public_key;
+        let session = MuSigSession::new(KeyPair::create(), &party2_pk, b"message");
+        let json = serde_json::to_string(&session.persist()).unwrap();
+
+        // a round one snapshot can not be resumed as round two
+        let state: SessionState = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            MuSigSession::<Round2>::from_persisted(state).err(),
+            Some(MuSigError::InvalidEncoding)
+        );
+        let state: SessionState = serde_json::from_str(&json).unwrap();
+        assert!(MuSigSession::<Round1>::from_persisted(state).is_ok());
+
+        // the ephemeral key does not open the stored commitment
+        let mut value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let other_commitment = EphemeralKey::create().commitment;
+        value["ephemeral_key"]["commitment"] = serde_json::to_value(&other_commitment).unwrap();
+        let state: SessionState = serde_json::from_value(value).unwrap();
+        assert_eq!(
+            MuSigSession::<Round1>::from_persisted(state).err(),
+            Some(MuSigError::CommitmentMismatch { index: None })
+        );
+    }
+
+    #[test]
+    fn test_signing_with_sha512() {
+        let message = b"message";
+        let keys: Vec<KeyPair> = (0..3).map(|_| KeyPair::create()).collect();
+        let pks: Vec<GE> = keys.iter().map(|key| key.public_key).collect();
+        let key_aggs: Vec<KeyAgg> = keys
+            .iter()
+            .map(|key| {
+                let index = KeyAgg::sorted_position(&pks, &key.public_key).unwrap();
+                KeyAgg::key_aggregation_n_with_hash::<HSha512>(&pks, index).unwrap()
+            })
+            .collect();
+        let apk = key_aggs[0].apk;
+        assert_ne!(apk, KeyAgg::key_aggregation_n(&pks, 0).unwrap().apk);
+
+        let mut ephemeral_keys: Vec<EphemeralKey> =
+            (0..3).map(|_| EphemeralKey::create()).collect();
+        let rs: Vec<GE> = ephemeral_keys
+            .iter()
+            .map(|key| key.keypair.public_key)
+            .collect();
+        let (r_hat, negated) = EphemeralKey::aggregate_ephemeral_pub_keys(&rs).unwrap();
+        if negated {
+            for ephemeral_key in ephemeral_keys.iter_mut() {
+                ephemeral_key.negate();
+            }
+        }
+        let c = EphemeralKey::hash_0_with_hash::<HSha512, _>(
+            &r_hat,
+            &apk,
+            message,
+            ChallengeMode::Musig,
+        );
+        let shares: Vec<BigInt> = ephemeral_keys
+            .iter()
+            .zip(&keys)
+            .zip(&key_aggs)
+            .map(|((ephemeral_key, key), key_agg)| {
+                EphemeralKey::sign(ephemeral_key, &c, key, &key_agg.hash)
+            })
+            .collect();
+        let signature = EphemeralKey::aggregate_signature_parts(&shares, &r_hat).unwrap();
+
+        assert!(signature
+            .verify_with_hash::<HSha512, _>(&apk, message, ChallengeMode::Musig)
+            .is_ok());
+        assert!(signature
+            .verify(&apk, message, ChallengeMode::Musig)
+            .is_err());
+    }
+
+    #[test]
+    fn test_negate_point_flips_y_parity() {
+        for _ in 0..8 {
+            let point = KeyPair::create().public_key;
+            let negated = negate_point(&point);
+            assert_eq!(negated.x_coor(), point.x_coor());
+            assert_ne!(point_has_even_y(&negated), point_has_even_y(&point));
+            assert_eq!(negate_point(&negated), point);
+            assert_eq!(bip340::has_even_y(&point), point_has_even_y(&point));
+        }
+    }
+
+    #[test]
+    fn test_verify_bytes_rejects_malformed_input() {
+        let vector = &TEST_VECTORS[0];
+        let signature = hex::decode(vector.signature).unwrap();
+        let apk = hex::decode(vector.public_key).unwrap();
+        let message = hex::decode(vector.message).unwrap();
+        let (r_x, s) = signature.split_at(32);
+        if !cfg!(feature = "prefixed_msg") && !cfg!(feature = "fixed_msg_hash") {
+            assert!(verify_bytes(s, r_x, &apk, &message, vector.mode).is_ok());
+        }
+
+        assert_eq!(
+            verify_bytes(&s[1..], r_x, &apk, &message, vector.mode),
+            Err(MuSigError::InvalidEncoding)
+        );
+        assert_eq!(
+            verify_bytes(s, r_x, &apk[..32], &message, vector.mode),
+            Err(MuSigError::InvalidEncoding)
+        );
+        assert_eq!(
+            verify_bytes(&[0xffu8; 32], r_x, &apk, &message, vector.mode),
+            Err(MuSigError::InvalidScalar)
+        );
+        assert_eq!(
+            verify_bytes(s, &[0xffu8; 32], &apk, &message, vector.mode),
+            Err(MuSigError::InvalidPoint)
+        );
+        let mut bad_prefix = apk.clone();
+        bad_prefix[0] = 5;
+        assert_eq!(
+            verify_bytes(s, r_x, &bad_prefix, &message, vector.mode),
+            Err(MuSigError::InvalidPoint)
+        );
+    }
+
+    #[test]
+    fn test_aggregate_partial_signatures() {
+        let message: [u8; 4] = [79, 77, 69, 82];
+        let keys: Vec<KeyPair> = (0..3).map(|_| KeyPair::create()).collect();
+        let pks: Vec<GE> = keys.iter().map(|key| key.public_key).collect();
+        let mut ephemeral_keys: Vec<EphemeralKey> =
+            (0..3).map(|_| EphemeralKey::create()).collect();
+        let rs: Vec<GE> = ephemeral_keys
+            .iter()
+            .map(|r| r.keypair.public_key)
+            .collect();
+        let (r_hat, negated) = EphemeralKey::aggregate_ephemeral_pub_keys(&rs).unwrap();
+        if negated {
+            for ephemeral_key in ephemeral_keys.iter_mut() {
+                ephemeral_key.negate();
+            }
+        }
+
+        let parts: Vec<PartialSignature> = keys
+            .iter()
+            .zip(&ephemeral_keys)
+            .map(|(key, ephemeral_key)| {
+                let index = KeyAgg::sorted_position(&pks, &key.public_key).unwrap();
+                let key_agg = KeyAgg::key_aggregation_n(&pks, index).unwrap();
+                let ctx = SigningContext::new(&r_hat, &key_agg, &message, ChallengeMode::Musig);
+                ephemeral_key.sign_round2(&ctx, key, index)
+            })
+            .collect();
+
+        // the coordinator only knows the key list and the parts
+        let apk = KeyAgg::aggregate_only(&pks).unwrap();
+        let c = EphemeralKey::hash_0(&r_hat, &apk, &message, ChallengeMode::Musig);
+        let mut sorted_pks = pks.clone();
+        sorted_pks.sort_by_key(|pk| pk.bytes_compressed_to_big_int());
+        for part in &parts {
+            let a_i = KeyAgg::coefficient(&pks, part.index).unwrap();
+            assert!(part.verify(&sorted_pks[part.index], &a_i, &c).is_ok());
+        }
+        let signature = PartialSignature::aggregate(&parts).unwrap();
+        assert!(signature
+            .verify(&apk, &message, ChallengeMode::Musig)
+            .is_ok());
+
+        let mut bad_parts = parts.clone();
+        bad_parts[0].s_i = (bad_parts[0].s_i.clone() + BigInt::from(1)) % FE::q();
+        let a_0 = KeyAgg::coefficient(&pks, bad_parts[0].index).unwrap();
+        assert!(bad_parts[0]
+            .verify(&sorted_pks[bad_parts[0].index], &a_0, &c)
+            .is_err());
+        assert!(PartialSignature::aggregate(&bad_parts)
+            .unwrap()
+            .verify(&apk, &message, ChallengeMode::Musig)
+            .is_err());
+        assert!(PartialSignature::aggregate(&[]).is_err());
+    }
+
+    #[test]
+    fn test_key_agg_as_map_key() {
+        let pks: Vec<GE> = (0..3).map(|_| KeyPair::create().public_key).collect();
+        let key_aggs: Vec<KeyAgg> = (0..3)
+            .map(|i| KeyAgg::key_aggregation_n(&pks, i).unwrap())
+            .collect();
+        assert_eq!(key_aggs[0], KeyAgg::key_aggregation_n(&pks, 0).unwrap());
+        assert_ne!(key_aggs[0], key_aggs[1]);
+
+        let mut sessions: HashMap<KeyAgg, usize> = HashMap::new();
+        for (i, key_agg) in key_aggs.into_iter().enumerate() {
+            sessions.insert(key_agg, i);
+        }
+        assert_eq!(sessions.len(), 3);
+        for i in 0..3 {
+            assert_eq!(sessions[&KeyAgg::key_aggregation_n(&pks, i).unwrap()], i);
+        }
+        let other_pks: Vec<GE> = (0..3).map(|_| KeyPair::create().public_key).collect();
+        assert!(!sessions.contains_key(&KeyAgg::key_aggregation_n(&other_pks, 0).unwrap()));
+    }
+
+    #[test]
+    fn test_key_agg_eq_compares_tweak() {
+        let pks: Vec<GE> = (0..3).map(|_| KeyPair::create().public_key).collect();
+        let key_agg = KeyAgg::key_aggregation_n(&pks, 0).unwrap();
+        let tweak = BigInt::from(5);
+        assert_eq!(key_agg.add_tweak(&tweak), key_agg.add_tweak(&tweak));
+        assert_ne!(key_agg.add_tweak(&tweak), key_agg);
+
+        // same apk and hash, only the tweak differs
+        let other_tweak = KeyAgg {
+            apk: key_agg.apk,
+            hash: key_agg.hash.clone(),
+            negated: key_agg.negated,
+            tweak,
+        };
+        assert_ne!(other_tweak, key_agg);
+    }
+
+    #[test]
+    fn test_verify_multisig() {
+        let message: [u8; 4] = [79, 77, 69, 82];
+        let (keys, _key_aggs, ephemeral_keys, _c, partial_sigs) =
+            n_party_partial_signatures(3, &message);
+        let rs: Vec<GE> = ephemeral_keys
+            .iter()
+            .map(|r| r.keypair.public_key)
+            .collect();
+        let (r_hat, _) = EphemeralKey::aggregate_ephemeral_pub_keys(&rs).unwrap();
+        let signature = EphemeralKey::aggregate_signature_parts(&partial_sigs, &r_hat).unwrap();
+        let mut pks: Vec<GE> = keys.iter().map(|key| key.public_key).collect();
+
+        assert!(verify_multisig(&signature, &pks, &message).is_ok());
+        pks.reverse();
+        assert!(verify_multisig(&signature, &pks, &message).is_ok());
+
+        pks[1] = KeyPair::create().public_key;
+        assert_eq!(
+            verify_multisig(&signature, &pks, &message),
+            Err(MuSigError::InvalidSignature)
+        );
+        assert_eq!(
+            verify_multisig(&signature, &[], &message),
+            Err(MuSigError::EmptyKeySet)
+        );
+    }
+
+    #[test]
+    fn test_key_aggregation_weighted() {
+        let message: [u8; 4] = [79, 77, 69, 82];
+        let keys: Vec<KeyPair> = (0..3).map(|_| KeyPair::create()).collect();
+        let pks: Vec<GE> = keys.iter().map(|key| key.public_key).collect();
+
+        let ones = vec![BigInt::from(1); 3];
+        for i in 0..3 {
+            assert_eq!(
+                KeyAgg::key_aggregation_weighted(&pks, &ones, i).unwrap(),
+                KeyAgg::key_aggregation_n(&pks, i).unwrap()
+            );
+        }
+
+        // a key of weight 0 does not change apk
+        let weights = vec![BigInt::from(1), BigInt::from(0), BigInt::from(1)];
+        let without_zero = [pks[0], pks[2]];
+        let zero_position = KeyAgg::sorted_position(&pks, &pks[1]).unwrap();
+        let key_agg = KeyAgg::key_aggregation_weighted(&pks, &weights, zero_position).unwrap();
+        assert_eq!(key_agg.apk, KeyAgg::aggregate_only(&without_zero).unwrap());
+        assert_eq!(key_agg.hash, BigInt::from(0));
+
+        // a full signing round with the weighted coefficients
+        let weights = vec![BigInt::from(2), BigInt::from(1), BigInt::from(5)];
+        let key_aggs: Vec<KeyAgg> = pks
+            .iter()
+            .map(|pk| {
+                let index = KeyAgg::sorted_position(&pks, pk).unwrap();
+                KeyAgg::key_aggregation_weighted(&pks, &weights, index).unwrap()
+            })
+            .collect();
+        assert_ne!(key_aggs[0].apk, KeyAgg::aggregate_only(&pks).unwrap());
+        let mut ephemeral_keys: Vec<EphemeralKey> =
+            (0..3).map(|_| EphemeralKey::create()).collect();
+        let rs: Vec<GE> = ephemeral_keys
+            .iter()
+            .map(|r| r.keypair.public_key)
+            .collect();
+        let (r_hat, negated) = EphemeralKey::aggregate_ephemeral_pub_keys(&rs).unwrap();
+        if negated {
+            for ephemeral_key in ephemeral_keys.iter_mut() {
+                ephemeral_key.negate();
+            }
+        }
+        let c = EphemeralKey::hash_0(&r_hat, &key_aggs[0].apk, &message, ChallengeMode::Musig);
+        let partial_sigs: Vec<BigInt> = (0..3)
+            .map(|i| EphemeralKey::sign(&ephemeral_keys[i], &c, &keys[i], &key_aggs[i].hash))
+            .collect();
+        let signature = EphemeralKey::aggregate_signature_parts(&partial_sigs, &r_hat).unwrap();
+        assert!(signature
+            .verify(&key_aggs[0].apk, &message, ChallengeMode::Musig)
+            .is_ok());
+
+        assert_eq!(
+            KeyAgg::key_aggregation_weighted(&pks, &weights[..2], 0).err(),
+            Some(MuSigError::IndexOutOfRange)
+        );
+        assert_eq!(
+            KeyAgg::key_aggregation_weighted(&pks, &[BigInt::from(0); 3], 0).err(),
+            Some(MuSigError::EmptyKeySet)
+        );
+        assert_eq!(
+            KeyAgg::key_aggregation_weighted(&pks, &[FE::q(), BigInt::from(1), BigInt::from(1)], 0)
+                .err(),
+            Some(MuSigError::InvalidScalar)
+        );
+    }
+
+    #[test]
+    fn test_nonce_tracker_flags_reuse() {
+        let message: [u8; 4] = [79, 77, 69, 82];
+        let party1_key = KeyPair::create();
+        let party2_key = KeyPair::create();
+        let key_agg = KeyAgg::key_aggregation(&party1_key.public_key, &party2_key.public_key);
+        let mut tracker = NonceTracker::new();
+
+        // the same nonce, derived twice from the key and the message
+        let mut ephemeral_key = EphemeralKey::create_from_private_key(&party1_key, &message);
+        let forced_copy = EphemeralKey::create_from_private_key(&party1_key, &message);
+        let c1 = BigInt::from(1);
+        let c2 = BigInt::from(2);
+        assert!(tracker
+            .sign(&ephemeral_key, &c1, &party1_key, &key_agg.hash)
+            .is_ok());
+        assert_eq!(
+            tracker.sign(&forced_copy, &c2, &party1_key, &key_agg.hash),
+            Err(MuSigError::NonceReuse)
+        );
+        ephemeral_key.negate();
+        assert_eq!(
+            tracker.sign(&ephemeral_key, &c2, &party1_key, &key_agg.hash),
+            Err(MuSigError::NonceReuse)
+        );
+
+        // a fresh nonce, or the same nonce under another key, is accepted
+        let fresh_key = EphemeralKey::create();
+        assert!(tracker
+            .sign(&fresh_key, &c2, &party1_key, &key_agg.hash)
+            .is_ok());
+        assert!(tracker
+            .check_and_record(&party2_key.public_key, &forced_copy.keypair.public_key)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_sign_with_external_nonce() {
+        let message: [u8; 4] = [79, 77, 69, 82];
+        let party1_key = KeyPair::create();
+        let party2_key = KeyPair::create();
+        let party1_key_agg =
+            KeyAgg::key_aggregation(&party1_key.public_key, &party2_key.public_key);
+        let party2_key_agg =
+            KeyAgg::key_aggregation(&party2_key.public_key, &party1_key.public_key);
+
+        // the secure element of party 1, simulated in software
+        let mut hsm_nonce = EphemeralKey::create();
+        let party2_ephemeral_key = EphemeralKey::create();
+        let (r_hat, negated) = EphemeralKey::aggregate_ephemeral_pub_keys(&[
+            hsm_nonce.keypair.public_key,
+            party2_ephemeral_key.keypair.public_key,
+        ])
+        .unwrap();
+        let party2_ephemeral_key = if negated {
+            hsm_nonce.negate();
+            let mut party2_ephemeral_key = party2_ephemeral_key;
+            party2_ephemeral_key.negate();
+            party2_ephemeral_key
+        } else {
+            party2_ephemeral_key
+        };
+        let c = EphemeralKey::hash_0(&r_hat, &party1_key_agg.apk, &message, ChallengeMode::Musig);
+        let expected = EphemeralKey::sign(&hsm_nonce, &c, &party1_key, &party1_key_agg.hash);
+
+        let external = EphemeralKey::from_external_nonce(hsm_nonce.keypair.public_key, |c| {
+            EphemeralKey::sign(&hsm_nonce, c, &party1_key, &party1_key_agg.hash)
+        });
+        assert!(EphemeralKey::test_com(
+            &external.r_pub,
+            &external.blind_factor,
+            &external.commitment
+        ));
+        let s1 = external.sign(&c).unwrap();
+        assert_eq!(s1, expected);
+
+        let s2 = EphemeralKey::sign(&party2_ephemeral_key, &c, &party2_key, &party2_key_agg.hash);
+        let signature = EphemeralKey::add_signature_parts(s1, &s2, &r_hat).unwrap();
+        assert!(signature
+            .verify(&party1_key_agg.apk, &message, ChallengeMode::Musig)
+            .is_ok());
+
+        let faulty = EphemeralKey::from_external_nonce(party2_key.public_key, |_| FE::q());
+        assert_eq!(faulty.sign(&c), Err(MuSigError::InvalidScalar));
+    }
+
+    #[test]
+    fn test_distinct_message_aggregate_signature() {
+        let messages: [&[u8]; 3] = [b"pay alice", b"pay bob", b"pay carol"];
+        let keys: Vec<KeyPair> = (0..3).map(|_| KeyPair::create()).collect();
+        let entries: Vec<(GE, Vec<u8>)> = keys
+            .iter()
+            .zip(messages.iter())
+            .map(|(key, message)| (key.public_key, message.to_vec()))
+            .collect();
+        let mut ephemeral_keys: Vec<EphemeralKey> =
+            (0..3).map(|_| EphemeralKey::create()).collect();
+        let rs: Vec<GE> = ephemeral_keys
+            .iter()
+            .map(|r| r.keypair.public_key)
+            .collect();
+        let (r_hat, negated) = EphemeralKey::aggregate_ephemeral_pub_keys(&rs).unwrap();
+        if negated {
+            for ephemeral_key in ephemeral_keys.iter_mut() {
+                ephemeral_key.negate();
+            }
+        }
+        let shares: Vec<BigInt> = (0..3)
+            .map(|i| {
+                let c = ias::challenge(&r_hat, &entries, i).unwrap();
+                EphemeralKey::sign(&ephemeral_keys[i], &c, &keys[i], &BigInt::from(1))
+            })
+            .collect();
+        let signature = EphemeralKey::aggregate_signature_parts(&shares, &r_hat).unwrap();
+        assert!(ias::verify_distinct_messages(&signature, &entries).is_ok());
+
+        let mut altered = entries.clone();
+        altered[1].1 = b"pay mallory".to_vec();
+        assert_eq!(
+            ias::verify_distinct_messages(&signature, &altered),
+            Err(MuSigError::InvalidSignature)
+        );
+        let mut reordered = entries.clone();
+        reordered.swap(0, 2);
+        assert!(ias::verify_distinct_messages(&signature, &reordered).is_err());
+        assert_eq!(
+            ias::verify_distinct_messages(&signature, &[]),
+            Err(MuSigError::EmptyKeySet)
+        );
+        assert_eq!(
+            ias::challenge(&r_hat, &entries, 3),
+            Err(MuSigError::IndexOutOfRange)
+        );
+    }
+
+    #[test]
+    fn test_key_pair_from_seed() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            hex::encode(hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+
+        let seed = [7u8; 64];
+        let key0 = KeyPair::from_seed(&seed, 0);
+        assert_eq!(key0.public_key, KeyPair::from_seed(&seed, 0).public_key);
+        assert_eq!(
+            key0.expose_secret(),
+            KeyPair::from_seed(&seed, 0).expose_secret()
+        );
+        let keys: Vec<GE> = (1..4)
+            .map(|index| KeyPair::from_seed(&seed, index).public_key)
+            .collect();
+        assert!(keys.iter().all(|pk| *pk != key0.public_key));
+        assert_ne!(keys[0], keys[1]);
+        assert_ne!(
+            KeyPair::from_seed(&[8u8; 64], 0).public_key,
+            key0.public_key
+        );
+    }
+
+    #[test]
+    fn test_sign_scalar_arithmetic_matches_big_int() {
+        let message: [u8; 4] = [79, 77, 69, 82];
+        let q = FE::q();
+        for _ in 0..8 {
+            let keypair = KeyPair::create();
+            let other_key = KeyPair::create();
+            let ephemeral_key = EphemeralKey::create();
+            let key_agg = KeyAgg::key_aggregation(&keypair.public_key, &other_key.public_key);
+            // a full 256 bit hash, not reduced mod q
+            let c = EphemeralKey::hash_0(
+                &ephemeral_key.keypair.public_key,
+                &key_agg.apk,
+                &message,
+                ChallengeMode::Musig,
+            );
+            let expected = BigInt::mod_add(
+                &ephemeral_key.keypair.expose_secret(),
+                &BigInt::mod_mul(
+                    &BigInt::mod_mul(&c, &keypair.expose_secret(), &q),
+                    &key_agg.hash,
+                    &q,
+                ),
+                &q,
+            );
+            assert_eq!(
+                EphemeralKey::sign(&ephemeral_key, &c, &keypair, &key_agg.hash),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_verify_rejects_zero_s_and_r_x_early() {
+        let message: [u8; 4] = [79, 77, 69, 82];
+        let keypair = KeyPair::create();
+        let signature = keypair.sign_single(&message);
+        let no_curve_math = |_: &FE| -> GE { panic!("sG computed") };
+
+        assert_eq!(
+            verify_with::<HSha256, _>(
+                &BigInt::from(0),
+                &signature.r_x,
+                &keypair.public_key,
+                &message,
+                ChallengeMode::Plain,
+                no_curve_math,
+            ),
+            Err(VerifyError::InvalidScalar)
+        );
+        assert_eq!(
+            verify_with::<HSha256, _>(
+                &signature.s,
+                &BigInt::from(0),
+                &keypair.public_key,
+                &message,
+                ChallengeMode::Plain,
+                no_curve_math,
+            ),
+            Err(VerifyError::InvalidPoint)
+        );
+
+        let zero_s = Signature {
+            s: BigInt::from(0),
+            r_x: signature.r_x.clone(),
+        };
+        assert_eq!(
+            zero_s.verify(&keypair.public_key, &message, ChallengeMode::Plain),
+            Err(MuSigError::InvalidScalar)
+        );
+        let zero_r_x = Signature {
+            s: signature.s.clone(),
+            r_x: BigInt::from(0),
+        };
+        assert_eq!(
+            zero_r_x.verify(&keypair.public_key, &message, ChallengeMode::Plain),
+            Err(MuSigError::InvalidPoint)
+        );
+    }
+
+    #[test]
+    fn test_signing_with_pedersen_commitments() {
+        let message: [u8; 4] = [79, 77, 69, 82];
+        let party1_key = KeyPair::create();
+        let party2_key = KeyPair::create();
+        let party1_key_agg =
+            KeyAgg::key_aggregation(&party1_key.public_key, &party2_key.public_key);
+        let party2_key_agg =
+            KeyAgg::key_aggregation(&party2_key.public_key, &party1_key.public_key);
+        let mut party1_ephemeral_key = EphemeralKey::<PedersenCommitment>::create_with_scheme();
+        let mut party2_ephemeral_key = EphemeralKey::<PedersenCommitment>::create_with_scheme();
+
+        // round two: each party opens its commitment
+        assert!(PedersenCommitment::verify(
+            &party1_ephemeral_key.keypair.public_key,
+            &party1_ephemeral_key.blind_factor,
+            &party1_ephemeral_key.commitment,
+        ));
+        assert!(PedersenCommitment::verify(
+            &party2_ephemeral_key.keypair.public_key,
+            &party2_ephemeral_key.blind_factor,
+            &party2_ephemeral_key.commitment,
+        ));
+        assert!(!PedersenCommitment::verify(
+            &party2_ephemeral_key.keypair.public_key,
+            &party1_ephemeral_key.blind_factor,
+            &party2_ephemeral_key.commitment,
+        ));
+
+        let (r_hat, negated) = EphemeralKey::aggregate_ephemeral_pub_keys(&[
+            party1_ephemeral_key.keypair.public_key,
+            party2_ephemeral_key.keypair.public_key,
+        ])
+        .unwrap();
+        if negated {
+            party1_ephemeral_key.negate();
+            party2_ephemeral_key.negate();
+        }
+        let c = EphemeralKey::hash_0(&r_hat, &party1_key_agg.apk, &message, ChallengeMode::Musig);
+        let s1 = EphemeralKey::sign(&party1_ephemeral_key, &c, &party1_key, &party1_key_agg.hash);
+        let s2 = EphemeralKey::sign(&party2_ephemeral_key, &c, &party2_key, &party2_key_agg.hash);
+        let signature = EphemeralKey::add_signature_parts(s1, &s2, &r_hat).unwrap();
+        assert!(signature
+            .verify(&party1_key_agg.apk, &message, ChallengeMode::Musig)
+            .is_ok());
+
+        let serialized = serde_json::to_string(&party1_ephemeral_key).unwrap();
+        let restored: EphemeralKey<PedersenCommitment> = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(restored.commitment, party1_ephemeral_key.commitment);
+    }
+
+    #[test]
+    fn test_hierarchical_key_aggregation() {
+        let message: [u8; 4] = [79, 77, 69, 82];
+        let keys: Vec<KeyPair> = (0..4).map(|_| KeyPair::create()).collect();
+        let subgroups: Vec<Vec<GE>> = vec![
+            vec![keys[0].public_key, keys[1].public_key],
+            vec![keys[2].public_key, keys[3].public_key],
+        ];
+        let subgroup_apks: Vec<GE> = subgroups
+            .iter()
+            .map(|pks| KeyAgg::aggregate_only(pks).unwrap())
+            .collect();
+
+        let key_aggs: Vec<KeyAgg> = keys
+            .iter()
+            .enumerate()
+            .map(|(i, key)| {
+                let pks = &subgroups[i / 2];
+                let member = KeyAgg::key_aggregation_n(
+                    pks,
+                    KeyAgg::sorted_position(pks, &key.public_key).unwrap(),
+                )
+                .unwrap();
+                let subgroup_index = KeyAgg::sorted_position(&subgroup_apks, &member.apk).unwrap();
+                KeyAgg::aggregate_subgroups(&subgroup_apks, subgroup_index)
+                    .unwrap()
+                    .with_member(&member)
+            })
+            .collect();
+        let apk = key_aggs[0].apk;
+        assert_eq!(apk, KeyAgg::aggregate_only(&subgroup_apks).unwrap());
+        assert!(key_aggs.iter().all(|key_agg| key_agg.apk == apk));
+
+        let mut ephemeral_keys: Vec<EphemeralKey> =
+            (0..4).map(|_| EphemeralKey::create()).collect();
+        let rs: Vec<GE> = ephemeral_keys
+            .iter()
+            .map(|r| r.keypair.public_key)
+            .collect();
+        let (r_hat, negated) = EphemeralKey::aggregate_ephemeral_pub_keys(&rs).unwrap();
+        if negated {
+            for ephemeral_key in ephemeral_keys.iter_mut() {
+                ephemeral_key.negate();
+            }
+        }
+        let c = EphemeralKey::hash_0(&r_hat, &apk, &message, ChallengeMode::Musig);
+        let partial_sigs: Vec<BigInt> = (0..4)
+            .map(|i| EphemeralKey::sign(&ephemeral_keys[i], &c, &keys[i], &key_aggs[i].hash))
+            .collect();
+        let signature = EphemeralKey::aggregate_signature_parts(&partial_sigs, &r_hat).unwrap();
+        assert!(signature
+            .verify(&apk, &message, ChallengeMode::Musig)
+            .is_ok());
+    }
+
+    #[cfg(feature = "unsafe_no_commitment")]
+    #[test]
+    fn test_direct_session_without_commitments() {
+        use protocols::aggsig::session::DirectSession;
+
+        let message: [u8; 4] = [79, 77, 69, 82];
+        let party1_key = KeyPair::create();
+        let party2_key = KeyPair::create();
+        let party1_pk = party1_key.public_key;
+        let party2_pk = party2_key.public_key;
+        let party1 = DirectSession::new(party1_key, &party2_pk, &message);
+        let party2 = DirectSession::new(party2_key, &party1_pk, &message);
+
+        let party1_r = party1.nonce();
+        let party2_r = party2.nonce();
+        let party1 = party1.receive_nonce(&party2_r);
+        let party2 = party2.receive_nonce(&party1_r);
+        let party1_partial_sig = party1.partial_signature();
+        let signature = party1.finalize(&party2.partial_signature()).unwrap();
+        assert_eq!(party2.finalize(&party1_partial_sig).unwrap(), signature);
+
+        let apk = KeyAgg::key_aggregation(&party1_pk, &party2_pk).apk;
+        assert!(signature
+            .verify(&apk, &message, ChallengeMode::Musig)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_duplicate_keys_rejected() {
+        let pks: Vec<GE> = (0..3).map(|_| KeyPair::create().public_key).collect();
+        let duplicated = vec![pks[0], pks[1], pks[0]];
+        assert_eq!(
+            KeyAgg::key_aggregation_n(&duplicated, 0).err(),
+            Some(MuSigError::DuplicateKey)
+        );
+        assert_eq!(
+            KeyAgg::key_aggregation_n_with_hash::<HSha512>(&duplicated, 0).err(),
+            Some(MuSigError::DuplicateKey)
+        );
+        assert_eq!(
+            KeyAgg::key_aggregation_weighted(&duplicated, &[BigInt::from(1); 3], 0).err(),
+            Some(MuSigError::DuplicateKey)
+        );
+        assert!(KeyAgg::key_aggregation_n(&pks, 0).is_ok());
+
+        let mut builder = KeyAggBuilder::new();
+        for pk in &duplicated {
+            builder.add_key(pk);
+        }
+        assert!(builder.has_duplicate_keys());
+
+        // the rare legitimate case
+        let key_agg = KeyAgg::key_aggregation_n_with_options(&duplicated, 0, true).unwrap();
+        assert_eq!(
+            KeyAgg::aggregate_only(&duplicated),
+            Err(MuSigError::DuplicateKey)
+        );
+        assert_eq!(
+            KeyAgg::key_aggregation_n_with_options(&pks, 1, false).unwrap(),
+            KeyAgg::key_aggregation_n(&pks, 1).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_verify_with_digest() {
+        let message: [u8; 4] = [79, 77, 69, 82];
+        let (_keys, key_aggs, ephemeral_keys, c, partial_sigs) =
+            n_party_partial_signatures(3, &message);
+        let rs: Vec<GE> = ephemeral_keys
+            .iter()
+            .map(|r| r.keypair.public_key)
+            .collect();
+        let (r_hat, _) = EphemeralKey::aggregate_ephemeral_pub_keys(&rs).unwrap();
+        let signature = EphemeralKey::aggregate_signature_parts(&partial_sigs, &r_hat).unwrap();
+        let apk = key_aggs[0].apk;
+
+        let digest = signature
+            .verify_with_digest(&apk, &message, ChallengeMode::Musig)
+            .unwrap();
+        assert_eq!(digest, c);
+        assert_eq!(
+            digest,
+            EphemeralKey::hash_0(&r_hat, &apk, &message, ChallengeMode::Musig)
+        );
+        assert_eq!(
+            signature.verify_with_digest(&apk, &[0u8], ChallengeMode::Musig),
+            Err(MuSigError::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn test_create_batch() {
+        let batch = EphemeralKey::create_batch(10);
+        assert_eq!(batch.len(), 10);
+        let mut rs: Vec<BigInt> = batch
+            .iter()
+            .map(|r| r.keypair.public_key.bytes_compressed_to_big_int())
+            .collect();
+        rs.sort();
+        rs.dedup();
+        assert_eq!(rs.len(), 10);
+        for r in &batch {
+            assert!(EphemeralKey::test_com(
+                &r.keypair.public_key,
+                &r.blind_factor,
+                &r.commitment
+            ));
+        }
+        assert!(EphemeralKey::create_batch(0).is_empty());
+
+        // the batch continues the seeded rng like create does
+        let seed = [9u8; 32];
+        let batch = with_seeded_rng(seed, || EphemeralKey::create_batch(2));
+        let (first, second) =
+            with_seeded_rng(seed, || (EphemeralKey::create(), EphemeralKey::create()));
+        assert_eq!(batch[0].keypair.public_key, first.keypair.public_key);
+        assert_eq!(batch[1].keypair.public_key, second.keypair.public_key);
+    }
+
+    #[cfg(any(feature = "prefixed_msg", feature = "fixed_msg_hash"))]
+    #[test]
+    fn test_messages_with_equal_big_int_value_do_not_collide() {
+        // BigInt::from reads both as the number 1
+        let short_message = [1u8];
+        let long_message = [0u8, 0, 0, 1];
+        assert_eq!(
+            BigInt::from(&short_message[..]),
+            BigInt::from(&long_message[..])
+        );
+        assert_ne!(
+            message_to_field_input(&short_message),
+            message_to_field_input(&long_message)
+        );
+        let keypair = KeyPair::create();
+        let signature = keypair.sign_single(&short_message);
+        assert!(signature
+            .verify(&keypair.public_key, &short_message, ChallengeMode::Plain)
+            .is_ok());
+        assert!(signature
+            .verify(&keypair.public_key, &long_message, ChallengeMode::Plain)
+            .is_err());
+    }
+
+    #[test]
+    fn test_apk_uncompressed_bytes() {
+        let pks: Vec<GE> = (0..3).map(|_| KeyPair::create().public_key).collect();
+        let key_agg = KeyAgg::key_aggregation_n(&pks, 0).unwrap();
+        let bytes = key_agg.apk_uncompressed_bytes();
+        assert_eq!(bytes.len(), 65);
+        assert_eq!(bytes[0], 0x04);
+        assert_eq!(bytes[1..33], key_agg.apk_bytes()[1..]);
+        assert_eq!(
+            *PublicKey::from_bytes(&bytes).unwrap().as_point(),
+            key_agg.apk
+        );
+    }
+
+    #[test]
+    fn test_verify_partial_2of2_detects_corrupted_peer_share() {
+        let message: [u8; 4] = [79, 77, 69, 82];
+        let (keys, key_aggs, ephemeral_keys, c, partial_sigs) =
+            n_party_partial_signatures(2, &message);
+        // each party checks the share received from the other one
+        for (me, other) in [(0, 1), (1, 0)].iter().cloned() {
+            assert!(verify_partial_2of2(
+                &partial_sigs[other],
+                &ephemeral_keys[other].keypair.public_key,
+                &keys[other].public_key,
+                &c,
+                &key_aggs[other].hash,
+            )
+            .is_ok());
+            assert!(verify_partial_2of2(
+                &partial_sigs[other],
+                &ephemeral_keys[other].keypair.public_key,
+                &keys[other].public_key,
+                &c,
+                &key_aggs[me].hash,
+            )
+            .is_err());
+        }
+
+        let corrupted = (partial_sigs[1].clone() + BigInt::from(1)) % FE::q();
+        assert_eq!(
+            verify_partial_2of2(
+                &corrupted,
+                &ephemeral_keys[1].keypair.public_key,
+                &keys[1].public_key,
+                &c,
+                &key_aggs[1].hash,
+            ),
+            Err(MuSigError::InvalidPartialSignature)
+        );
+    }
+
+    #[test]
+    fn test_compute_challenge_fe_matches_hash_0() {
+        let message: [u8; 4] = [79, 77, 69, 82];
+        let r = KeyPair::create().public_key;
+        let apk = KeyPair::create().public_key;
+        for mode in [ChallengeMode::Musig, ChallengeMode::Plain].iter().cloned() {
+            let c = EphemeralKey::hash_0(&r, &apk, &message, mode);
+            let c_fe = compute_challenge_fe(&r, &apk, &message, mode);
+            let expected: FE = ECScalar::from(&c);
+            assert_eq!(c_fe, expected);
+            assert_eq!(c_fe.to_big_int(), c % FE::q());
+            assert_eq!(
+                challenge_negate(&c_fe).to_big_int(),
+                FE::q() - c_fe.to_big_int()
+            );
+        }
+    }
+
+    #[test]
+    fn test_challenge_mode_prefixes() {
+        let message: [u8; 4] = [79, 77, 69, 82];
+        let r = KeyPair::create().public_key;
+        let apk = KeyPair::create().public_key;
+        let r_x = r.x_coor().unwrap();
+        let msg = message_to_field_input(&message);
+        let apk_int = apk.bytes_compressed_to_big_int();
+        assert_eq!(
+            EphemeralKey::hash_0(&r, &apk, &message, ChallengeMode::MusigLegacy),
+            HSha256::create_hash(&[&BigInt::from(0), &r_x, &apk_int, &msg])
+        );
+        assert_eq!(
+            EphemeralKey::hash_0(&r, &apk, &message, ChallengeMode::Plain),
+            HSha256::create_hash(&[&r_x, &apk_int, &msg])
+        );
+        assert_eq!(ChallengeMode::Plain.prefix(), None);
+        assert_eq!(
+            ChallengeMode::Musig.prefix() == ChallengeMode::MusigLegacy.prefix(),
+            cfg!(feature = "legacy_hashes")
+        );
+
+        // a signature only verifies under the mode it was made with
+        let modes = [
+            ChallengeMode::Musig,
+            ChallengeMode::MusigLegacy,
+            ChallengeMode::Plain,
+        ];
+        let keypair = KeyPair::create();
+        for sign_mode in modes.iter().cloned() {
+            let ephemeral_key = EphemeralKey::create();
+            let r = ephemeral_key.keypair.public_key;
+            let c = EphemeralKey::hash_0(&r, &keypair.public_key, &message, sign_mode);
+            let s = EphemeralKey::sign(&ephemeral_key, &c, &keypair, &BigInt::from(1));
+            let signature = EphemeralKey::add_signature_parts(s, &BigInt::from(0), &r).unwrap();
+            for verify_mode in modes.iter().cloned() {
+                let same_challenge = sign_mode.prefix() == verify_mode.prefix();
+                assert_eq!(
+                    signature
+                        .verify(&keypair.public_key, &message, verify_mode)
+                        .is_ok(),
+                    same_challenge
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_signer_set_rejects_foreign_handle() {
+        let message: [u8; 4] = [79, 77, 69, 82];
+        let keys: Vec<KeyPair> = (0..3).map(|_| KeyPair::create()).collect();
+        let pks: Vec<GE> = keys.iter().map(|key| key.public_key).collect();
+        let signer_set = SignerSet::new(&pks).unwrap();
+        let other_set = SignerSet::new(&pks).unwrap();
+
+        let handle = signer_set.handle(&keys[0].public_key).unwrap();
+        assert_eq!(
+            handle.index(),
+            KeyAgg::sorted_position(&pks, &keys[0].public_key).unwrap()
+        );
+        assert_eq!(
+            signer_set.key_aggregation(&handle).unwrap(),
+            KeyAgg::key_aggregation_n(&pks, handle.index()).unwrap()
+        );
+        assert_eq!(
+            other_set.key_aggregation(&handle).unwrap_err(),
+            MuSigError::HandleMismatch
+        );
+
+        let ephemeral_key = EphemeralKey::create();
+        let c = EphemeralKey::hash_0(
+            &ephemeral_key.keypair.public_key,
+            &signer_set.key_aggregation(&handle).unwrap().apk,
+            &message,
+            true,
+        );
+        assert!(signer_set
+            .sign(&handle, &ephemeral_key, &c, &keys[0])
+            .is_ok());
+        assert_eq!(
+            other_set
+                .sign(&handle, &ephemeral_key, &c, &keys[0])
+                .unwrap_err(),
+            MuSigError::HandleMismatch
+        );
+        assert_eq!(
+            signer_set
+                .sign(&handle, &ephemeral_key, &c, &keys[1])
+                .unwrap_err(),
+            MuSigError::HandleMismatch
+        );
+
+        assert!(signer_set.handle(&KeyPair::create().public_key).is_none());
+        assert_eq!(SignerSet::new(&[]).unwrap_err(), MuSigError::EmptyKeySet);
+        assert_eq!(
+            SignerSet::new(&[pks[0], pks[0]]).unwrap_err(),
+            MuSigError::DuplicateKey
+        );
+    }
+
+    #[test]
+    fn test_signature_der_round_trip() {
+        let message: [u8; 4] = [79, 77, 69, 82];
+        for _ in 0..8 {
+            let keypair = KeyPair::create();
+            let signature = keypair.sign_single(&message);
+            let der = signature.to_der();
+            assert_eq!(der[0], 0x30);
+            assert_eq!(der[1] as usize, der.len() - 2);
+            assert!(der.len() <= 72);
+            let decoded = Signature::from_der(&der).unwrap();
+            assert_eq!(decoded, signature);
+            assert!(decoded
+                .verify(&keypair.public_key, &message, ChallengeMode::Plain)
+                .is_ok());
+        }
+
+        // a high bit gets a 00 in front, small values are not padded
+        let signature = Signature {
+            r_x: BigInt::from(0x80),
+            s: BigInt::from(1),
+        };
+        let der = signature.to_der();
+        assert_eq!(
+            der,
+            vec![0x30, 0x07, 0x02, 0x02, 0x00, 0x80, 0x02, 0x01, 0x01]
+        );
+        assert_eq!(Signature::from_der(&der).unwrap(), signature);
+    }
+
+    #[test]
+    fn test_signature_from_der_rejects_non_der() {
+        let valid = vec![0x30, 0x07, 0x02, 0x02, 0x00, 0x80, 0x02, 0x01, 0x01];
+        assert!(Signature::from_der(&valid).is_ok());
+        let invalid: Vec<Vec<u8>> = vec![
+            // non-minimal integers
+            vec![0x30, 0x07, 0x02, 0x02, 0x00, 0x01, 0x02, 0x01, 0x01],
+            vec![0x30, 0x08, 0x02, 0x03, 0x00, 0x00, 0x80, 0x02, 0x01, 0x01],
+            // negative r_x
+            vec![0x30, 0x06, 0x02, 0x01, 0x80, 0x02, 0x01, 0x01],
+            // empty integer
+            vec![0x30, 0x05, 0x02, 0x00, 0x02, 0x01, 0x01],
+            // trailing byte after the sequence, and inside it
+            vec![0x30, 0x07, 0x02, 0x02, 0x00, 0x80, 0x02, 0x01, 0x01, 0x00],
+            vec![0x30, 0x08, 0x02, 0x02, 0x00, 0x80, 0x02, 0x01, 0x01, 0x00],
+            // long form sequence length
+            vec![0x81, 0x07, 0x02, 0x02, 0x00, 0x80, 0x02, 0x01, 0x01],
+            vec![0x30, 0x81, 0x07, 0x02, 0x02, 0x00, 0x80, 0x02, 0x01, 0x01],
+            // wrong tags, truncated
+            vec![0x31, 0x07, 0x02, 0x02, 0x00, 0x80, 0x02, 0x01, 0x01],
+            vec![0x30, 0x07, 0x02, 0x02, 0x00, 0x80, 0x03, 0x01, 0x01],
+            vec![0x30, 0x04, 0x02, 0x02, 0x00, 0x80],
+            vec![],
+        ];
+        for bytes in invalid.iter() {
+            assert_eq!(
+                Signature::from_der(bytes).unwrap_err(),
+                MuSigError::InvalidEncoding
+            );
+        }
+
+        // s = q is well formed DER but out of range
+        let signature = Signature {
+            r_x: BigInt::from(1),
+            s: curve_order(),
+        };
+        assert_eq!(
+            Signature::from_der(&signature.to_der()).unwrap_err(),
+            MuSigError::InvalidScalar
+        );
+    }
+
+    #[test]
+    fn test_verify_xonly() {
+        let message: [u8; 4] = [79, 77, 69, 82];
+        let (_keys, key_aggs, ephemeral_keys, _c, partial_sigs) =
+            n_party_partial_signatures(3, &message);
+        let rs: Vec<GE> = ephemeral_keys
+            .iter()
+            .map(|r| r.keypair.public_key)
+            .collect();
+        let (r_hat, _) = EphemeralKey::aggregate_ephemeral_pub_keys(&rs).unwrap();
+        let signature = EphemeralKey::aggregate_signature_parts(&partial_sigs, &r_hat).unwrap();
+
+        let apk_x = bip340::xonly_bytes(&key_aggs[0].apk);
+        assert_eq!(bip340::lift_x(&apk_x).unwrap(), key_aggs[0].apk);
+        assert!(verify_xonly(&signature, &apk_x, &message).is_ok());
+        assert!(verify_xonly(&signature, &apk_x, &[0u8; 4]).is_err());
+
+        // x = 0 and x >= p are not on the curve
+        assert_eq!(
+            verify_xonly(&signature, &[0u8; 32], &message).unwrap_err(),
+            MuSigError::InvalidPoint
+        );
+        assert_eq!(
+            verify_xonly(&signature, &[0xffu8; 32], &message).unwrap_err(),
+            MuSigError::InvalidPoint
+        );
+    }
+
+    #[test]
+    fn test_cached_verifier_matches_verify() {
+        let message: [u8; 4] = [79, 77, 69, 82];
+        let (_keys, key_aggs, ephemeral_keys, _c, partial_sigs) =
+            n_party_partial_signatures(3, &message);
+        let rs: Vec<GE> = ephemeral_keys
+            .iter()
+            .map(|r| r.keypair.public_key)
+            .collect();
+        let (r_hat, _) = EphemeralKey::aggregate_ephemeral_pub_keys(&rs).unwrap();
+        let signature = EphemeralKey::aggregate_signature_parts(&partial_sigs, &r_hat).unwrap();
+        let apk = key_aggs[0].apk;
+        let verifier = CachedVerifier::new(apk);
+        assert_eq!(*verifier.apk(), apk);
+
+        let mut tampered = signature.clone();
+        tampered.s = (tampered.s + BigInt::from(1)) % FE::q();
+        let other_message = [0u8; 4];
+        let cases: Vec<(&Signature, &[u8])> = vec![
+            (&signature, &message),
+            (&signature, &other_message),
+            (&tampered, &message),
+        ];
+        for (sig, msg) in cases {
+            for mode in [ChallengeMode::Musig, ChallengeMode::Plain].iter().cloned() {
+                assert_eq!(verifier.verify(sig, msg, mode), sig.verify(&apk, msg, mode));
+            }
+        }
+        assert!(verifier.verify(&signature, &message, true).is_ok());
+    }
+
+    #[test]
+    fn test_message_as_ref_types_give_identical_results() {
+        let text = String::from("OMER");
+        let vec: Vec<u8> = text.as_bytes().to_vec();
+        let array: [u8; 4] = [79, 77, 69, 82];
+        let slice: &[u8] = &array;
+        let keypair = KeyPair::create();
+        let r = KeyPair::create().public_key;
+
+        let c = EphemeralKey::hash_0(&r, &keypair.public_key, slice, ChallengeMode::Plain);
+        assert_eq!(
+            EphemeralKey::hash_0(&r, &keypair.public_key, &text, ChallengeMode::Plain),
+            c
+        );
+        assert_eq!(
+            EphemeralKey::hash_0(&r, &keypair.public_key, &vec, ChallengeMode::Plain),
+            c
+        );
+        assert_eq!(
+            EphemeralKey::hash_0(&r, &keypair.public_key, array, ChallengeMode::Plain),
+            c
+        );
+
+        let verifier = Verifier::new();
+        let cached_verifier = CachedVerifier::new(keypair.public_key);
+        for signature in [
+            keypair.sign_single(&text),
+            keypair.sign_single(vec.clone()),
+            keypair.sign_single(slice),
+        ]
+        .iter()
+        {
+            assert!(signature
+                .verify(&keypair.public_key, &text, ChallengeMode::Plain)
+                .is_ok());
+            assert!(signature
+                .verify(&keypair.public_key, &vec, ChallengeMode::Plain)
+                .is_ok());
+            assert!(signature
+                .verify(&keypair.public_key, slice, ChallengeMode::Plain)
+                .is_ok());
+            assert!(verifier
+                .verify(signature, &keypair.public_key, &text, false)
+                .is_ok());
+            assert!(cached_verifier.verify(signature, &vec, false).is_ok());
+            assert!(signature
+                .verify(&keypair.public_key, "OMER!", ChallengeMode::Plain)
+                .is_err());
+        }
+    }
+
+    // runs the DKG between n parties, with tamper applied to the shares party 0 receives
+    fn run_dkg<F: Fn(&mut Vec<FE>)>(
+        params: &ThresholdParams,
+        tamper: F,
+    ) -> Vec<Result<ThresholdKeyPair, MuSigError>> {
+        let (states, round1_messages): (Vec<_>, Vec<_>) =
+            (0..params.n).map(|i| dkg::round1(params, i)).unzip();
+        let mut states2 = Vec::new();
+        let mut openings = Vec::new();
+        let mut sent_shares = Vec::new();
+        for state in states {
+            let (state2, opening, shares) = dkg::round2(state, &round1_messages).unwrap();
+            states2.push(state2);
+            openings.push(opening);
+            sent_shares.push(shares);
+        }
+        states2
+            .into_iter()
+            .enumerate()
+            .map(|(i, state)| {
+                let mut received: Vec<FE> = sent_shares.iter().map(|shares| shares[i]).collect();
+                if i == 0 {
+                    tamper(&mut received);
+                }
+                dkg::finalize(state, &openings, &received)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_dkg_3_parties() {
+        let params = ThresholdParams { t: 2, n: 3 };
+        let keys: Vec<ThresholdKeyPair> = run_dkg(&params, |_| {})
+            .into_iter()
+            .map(|key| key.unwrap())
+            .collect();
+        let group_public_key = keys[0].group_public_key;
+        assert!(keys
+            .iter()
+            .all(|key| key.group_public_key == group_public_key));
+        threshold_sign_and_verify_with_keys(&keys, &[0, 1]);
+        threshold_sign_and_verify_with_keys(&keys, &[0, 2]);
+        threshold_sign_and_verify_with_keys(&keys, &[1, 2]);
+
+        // a share that does not match the commitments of its sender is detected
+        let results = run_dkg(&params, |shares| {
+            let one: FE = ECScalar::from(&BigInt::from(1));
+            shares[2] = shares[2] + one;
+        });
+        assert_eq!(
+            results[0].as_ref().unwrap_err(),
+            &MuSigError::CommitmentMismatch { index: Some(2) }
+        );
+        assert!(results[1].is_ok() && results[2].is_ok());
+    }
+
+    #[test]
+    fn test_reconstruct_secret_from_any_t_shares() {
+        let params = ThresholdParams { t: 3, n: 5 };
+        let keypair = KeyPair::create();
+        let (_vss, shares) = keypair.share_private_key(&params);
+        let shares: Vec<(usize, BigInt)> = shares
+            .iter()
+            .enumerate()
+            .map(|(i, share)| (i, share.to_big_int()))
+            .collect();
+        for a in 0..5 {
+            for b in a + 1..5 {
+                for c in b + 1..5 {
+                    let subset = [shares[c].clone(), shares[a].clone(), shares[b].clone()];
+                    assert_eq!(
+                        reconstruct_secret(&params, &subset).unwrap(),
+                        keypair.expose_secret()
+                    );
+                }
+            }
+        }
+        assert_eq!(
+            reconstruct_secret(&params, &shares).unwrap(),
+            keypair.expose_secret()
+        );
+
+        assert_eq!(
+            reconstruct_secret(&params, &[]).unwrap_err(),
+            MuSigError::EmptyKeySet
+        );
+        assert_eq!(
+            reconstruct_secret(
+                &params,
+                &[shares[0].clone(), shares[1].clone(), shares[0].clone()]
+            )
+            .unwrap_err(),
+            MuSigError::DuplicateKey
+        );
+        let mut out_of_range = shares[..3].to_vec();
+        out_of_range[2].0 = 5;
+        assert_eq!(
+            reconstruct_secret(&params, &out_of_range).unwrap_err(),
+            MuSigError::IndexOutOfRange
+        );
+        out_of_range[2].0 = usize::max_value();
+        assert_eq!(
+            reconstruct_secret(&params, &out_of_range).unwrap_err(),
+            MuSigError::IndexOutOfRange
+        );
+        let mut unreduced = shares[..3].to_vec();
+        unreduced[0].1 = curve_order();
+        assert_eq!(
+            reconstruct_secret(&params, &unreduced).unwrap_err(),
+            MuSigError::InvalidScalar
+        );
+    }
+
+    #[test]
+    fn test_reconstruct_secret_rejects_fewer_than_t_shares() {
+        let params = ThresholdParams { t: 3, n: 5 };
+        let keypair = KeyPair::create();
+        let (_vss, shares) = keypair.share_private_key(&params);
+        let shares: Vec<(usize, BigInt)> = shares
+            .iter()
+            .enumerate()
+            .map(|(i, share)| (i, share.to_big_int()))
+            .collect();
+        // t - 1 shares would interpolate to some other value
+        assert_eq!(
+            reconstruct_secret(&params, &shares[..2]).unwrap_err(),
+            MuSigError::NotEnoughShares
+        );
+        assert_eq!(
+            reconstruct_secret(&params, &shares[3..4]).unwrap_err(),
+            MuSigError::NotEnoughShares
+        );
+    }
+
+    #[test]
+    fn test_reconstruct_secret_after_dkg() {
+        let params = ThresholdParams { t: 2, n: 3 };
+        let keys: Vec<ThresholdKeyPair> = run_dkg(&params, |_| {})
+            .into_iter()
+            .map(|key| key.unwrap())
+            .collect();
+        let group_public_key = keys[0].group_public_key;
+        for signers in [[0, 1], [0, 2], [2, 1]].iter() {
+            let quorum: Vec<(usize, BigInt)> = signers
+                .iter()
+                .map(|&i| (keys[i].party_index, keys[i].keypair.expose_secret()))
+                .collect();
+            let secret = reconstruct_secret(&params, &quorum).unwrap();
+            assert_eq!(
+                KeyPair::create_from_private_key(&secret)
+                    .unwrap()
+                    .public_key,
+                group_public_key
+            );
+        }
     }
 }
@@ -0,0 +1,136 @@
+/*
+    Multisig Schnorr
+
+    Copyright 2018 by Kzen Networks
+
+    This file is part of Multisig Schnorr library
+    (https://github.com/KZen-networks/multisig-schnorr)
+
+    Multisig Schnorr is free software: you can redistribute
+    it and/or modify it under the terms of the GNU General Public
+    License as published by the Free Software Foundation, either
+    version 3 of the License, or (at your option) any later version.
+
+    @license GPL-3.0+ <https://github.com/KZen-networks/multisig-schnorr/blob/master/LICENSE>
+*/
+
+//! Fixed signatures made by this crate, to pin the wire format for other implementations.
+//!
+//! All values are hex encoded. `public_key` is the 33 byte compressed SEC1 encoding of the
+//! signer's key, or of apk for `ChallengeMode::Musig`, see `KeyAgg::key_aggregation_n`.
+//! `signature` is `Signature::to_bytes`: the 32 byte big endian r_x followed by the 32 byte
+//! big endian s.
+//!
+//! The challenge is c = SHA256(prefix || int(r_x) || P || int(m)) mod q, where P is the 33 byte
+//! compressed key and int(x) is the minimal big endian encoding of x read as an unsigned
+//! integer: leading zero bytes are dropped, also from the message, and zero is the single byte
+//! 00. The prefix is empty for `ChallengeMode::Plain` and the ASCII bytes of "musig/chal" for
+//! `ChallengeMode::Musig`. A signature is valid iff the x coordinate of s·G - c·P is r_x.
+//!
+//! The key aggregation coefficient of pk_i is a_i = SHA256("musig/agg" || pk_i || pk_1 || ...
+//! || pk_n) with the compressed keys sorted bytewise, apk = sum of a_i·pk_i, negated together
+//! with all a_i if its y is odd. The vectors use the default message encoding, not
+//! prefixed_msg or fixed_msg_hash, and the musig vectors the default tags and coefficients,
+//! not legacy_hashes or musig_spec_v1.
+use curv::BigInt;
+
+use protocols::aggsig::keys::PublicKey;
+use protocols::aggsig::{ChallengeMode, MuSigError, Signature};
+
+pub struct TestVector {
+    // the private keys of all signers, hex encoded 32 byte scalars
+    pub private_keys: &'static [&'static str],
+    pub public_key: &'static str,
+    pub message: &'static str,
+    pub signature: &'static str,
+    pub mode: ChallengeMode,
+}
+
+pub const TEST_VECTORS: &[TestVector] = &[
+    TestVector {
+        private_keys: &[
+            "0000000000000000000000000000000000000000000000000000000000000003",
+        ],
+        public_key: "02f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9",
+        message: "",
+        signature:
+            "5cbdf0646e5db4eaa398f365f2ea7a0e3d419b7e0330e39ce92bddedcac4f9bc192f7f2739822d9b1dcb6a9ac681a4611c25d3441ac9ad01e0b706859bb5241a",
+        mode: ChallengeMode::Plain,
+    },
+    TestVector {
+        private_keys: &[
+            "b7e151628aed2a6abf7158809cf4f3c762e7160f38b4da56a784d9045190cfef",
+        ],
+        public_key: "02dff1d77f2a671c5f36183726db2341be58feae1da2deced843240f7b502ba659",
+        message: "00006d6573736167652077697468206c656164696e67207a65726f206279746573",
+        signature:
+            "bb50e2d89a4ed70663d080659fe0ad4b9bc3e06c17a227433966cb59ceee020d5db4b0bf2faf7613d1ca60e48302b6ff4baebc9b8daa08f842ad2e29639abf91",
+        mode: ChallengeMode::Plain,
+    },
+    TestVector {
+        private_keys: &[
+            "fffffffffffffffffffffffffffffffebaaedce6af48a03bbfd25e8cd0364140",
+        ],
+        public_key: "0379be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+        message: "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f",
+        signature:
+            "c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5f6e4a365f6c46690e1d2900641fae0f867a4f1b14e2152b726ee2ffd636e0423",
+        mode: ChallengeMode::Plain,
+    },
+    TestVector {
+        private_keys: &[
+            "000000000000000000000000000000000000000000000000000000000000000b",
+            "0000000000000000000000000000000000000000000000000000000000000016",
+        ],
+        public_key: "02ad9b60ad89b1ea7f7078452cbe7a6dc314cb1da64640401cf554c1bc44a4078f",
+        message: "4d755369672074776f207061727479",
+        signature:
+            "59dbf46f8c94759ba21277c33784f41645f7b44f6c596a58ce92e666191abe3e0133de9916d595e8961987bf328278bab522492ad7e25825edc21752f547c012",
+        mode: ChallengeMode::Musig,
+    },
+    TestVector {
+        private_keys: &[
+            "0000000000000000000000000000000000000000000000000000000000000010",
+            "0000000000000000000000000000000000000000000000000000000000000020",
+            "0000000000000000000000000000000000000000000000000000000000000030",
+        ],
+        public_key: "02f1ef6f832fee6e636a8e979f71451b8071daa863abd1d323daa567168be25654",
+        message: "4f4d4552",
+        signature:
+            "ddc1fc1916d3a18157ee96c63484570a531cbaef54661849f4cf40fe2e2aedf697b014970e94a7d80855a66c3eaa13f88c538cbab61df96edb891799b867c433",
+        mode: ChallengeMode::Musig,
+    },
+];
+
+// decodes a vector and checks it with Signature::verify
+pub fn verify_vector(vector: &TestVector) -> Result<(), MuSigError> {
+    let public_key = PublicKey::from_bytes(&decode_hex(vector.public_key)?)?;
+    let message = decode_hex(vector.message)?;
+    let signature_bytes = decode_hex(vector.signature)?;
+    if signature_bytes.len() != 64 {
+        return Err(MuSigError::InvalidEncoding);
+    }
+    let mut bytes = [0u8; 64];
+    bytes.copy_from_slice(&signature_bytes);
+    let signature = Signature::from_bytes(&bytes)?;
+    signature.verify(public_key.as_point(), &message, vector.mode)
+}
+
+// the private keys of a vector
+pub fn private_keys(vector: &TestVector) -> Vec<BigInt> {
+    vector
+        .private_keys
+        .iter()
+        .map(|private_key| BigInt::from(&decode_hex(private_key).unwrap()[..]))
+        .collect()
+}
+
+fn decode_hex(hex: &str) -> Result<Vec<u8>, MuSigError> {
+    if hex.len() % 2 != 0 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(MuSigError::InvalidEncoding);
+    }
+    Ok((0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect())
+}
@@ -0,0 +1,151 @@
+/*
+    Multisig Schnorr
+
+    Copyright 2018 by Kzen Networks
+
+    This file is part of Multisig Schnorr library
+    (https://github.com/KZen-networks/multisig-schnorr)
+
+    Multisig Schnorr is free software: you can redistribute
+    it and/or modify it under the terms of the GNU General Public
+    License as published by the Free Software Foundation, either
+    version 3 of the License, or (at your option) any later version.
+
+    @license GPL-3.0+ <https://github.com/KZen-networks/multisig-schnorr/blob/master/LICENSE>
+*/
+
+//! {t,n} signing on top of the aggregated signature flow.
+//!
+//! Every party shares a random secret with Feldman VSS, the group key is the sum of the
+//! shared secrets and each party holds the sum of the shares it received. Any t parties can
+//! then sign: the Lagrange coefficient of a signer with respect to the active signer set takes
+//! the place of the key aggregation coefficient in `EphemeralKey::sign`, so the resulting
+//! signature verifies under the group key with the usual `verify`.
+use curv::cryptographic_primitives::secret_sharing::feldman_vss::VerifiableSS;
+use curv::elliptic::curves::traits::*;
+use curv::{BigInt, FE, GE};
+
+use protocols::aggsig::{curve_order, EphemeralKey, KeyPair, MuSigError};
+use std::convert::TryFrom;
+use Error::{self, InvalidSS};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ThresholdParams {
+    pub t: usize, // number of signers required
+    pub n: usize, // number of parties holding a share
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ThresholdKeyPair {
+    pub party_index: usize,
+    pub group_public_key: GE,
+    // keypair of the local share x_i of the group secret
+    pub keypair: KeyPair,
+}
+
+impl KeyPair {
+    // shares the private key between n parties such that any t of them can reconstruct it
+    pub fn share_private_key(&self, params: &ThresholdParams) -> (VerifiableSS, Vec<FE>) {
+        assert!(params.t > 0 && params.t <= params.n);
+        VerifiableSS::share(params.t - 1, params.n, &self.private_key.to_scalar())
+    }
+}
+
+impl ThresholdKeyPair {
+    // vss_vec[j] and secret_shares[j] are the commitments and the share received from party j
+    pub fn construct(
+        params: &ThresholdParams,
+        party_index: usize,
+        vss_vec: &[VerifiableSS],
+        secret_shares: &[FE],
+    ) -> Result<ThresholdKeyPair, Error> {
+        assert_eq!(vss_vec.len(), params.n);
+        assert_eq!(secret_shares.len(), params.n);
+        assert!(party_index < params.n);
+
+        let correct_ss_verify = vss_vec
+            .iter()
+            .zip(secret_shares)
+            .all(|(vss, share)| vss.validate_share(share, party_index + 1).is_ok());
+        if !correct_ss_verify {
+            return Err(InvalidSS);
+        }
+
+        let mut commitments_iter = vss_vec.iter().map(|vss| vss.commitments[0]);
+        let head = commitments_iter.next().unwrap();
+        let group_public_key = commitments_iter.fold(head, |acc, y| acc + y);
+        let x_i = secret_shares.iter().fold(FE::zero(), |acc, x| acc + x);
+        // shares that sum to zero are no usable key share
+        let keypair = KeyPair::from_secret_scalar(x_i).map_err(|_| InvalidSS)?;
+        Ok(ThresholdKeyPair {
+            party_index,
+            group_public_key,
+            keypair,
+        })
+    }
+
+    // partial signature of this party; signers holds the indices of all active signers
+    pub fn sign(&self, r: &EphemeralKey, c: &BigInt, signers: &[usize]) -> BigInt {
+        let lambda = lagrange_coefficient(self.party_index, signers);
+        EphemeralKey::sign(r, c, &self.keypair, &lambda.to_big_int())
+    }
+}
+
+// Lagrange coefficient at x = 0 of party_index with respect to the signer set.
+// Party i holds the evaluation of the sharing polynomials at i + 1.
+pub fn lagrange_coefficient(party_index: usize, signers: &[usize]) -> FE {
+    assert!(signers.contains(&party_index));
+    // i + 1 is computed on the BigInt, so no index wraps around to x = 0
+    let point = |i: usize| -> FE {
+        let i = u64::try_from(i).expect("party index does not fit in u64");
+        ECScalar::from(&(BigInt::from(i) + BigInt::from(1)))
+    };
+    let x_i = point(party_index);
+    signers.iter().filter(|&&j| j != party_index).fold(
+        ECScalar::from(&BigInt::from(1)),
+        |acc: FE, &j| {
+            let x_j = point(j);
+            let denominator = x_j.sub(&x_i.get_element());
+            acc * x_j * denominator.invert()
+        },
+    )
+}
+
+// Recovers the group private key from the shares (party_index, x_i) of some parties by
+// Lagrange interpolation at x = 0, e.g. from the ThresholdKeyPairs of a quorum after a
+// disaster. Fewer than params.t shares give NotEnoughShares, as any such set interpolates to
+// some other value, a party index of n or more gives IndexOutOfRange and a repeated one
+// DuplicateKey. Wrong share values can not be detected here, so check
+// KeyPair::create_from_private_key(&secret)?.public_key against the group public key.
+pub fn reconstruct_secret(
+    params: &ThresholdParams,
+    shares: &[(usize, BigInt)],
+) -> Result<BigInt, MuSigError> {
+    if shares.is_empty() {
+        return Err(MuSigError::EmptyKeySet);
+    }
+    if shares.len() < params.t {
+        return Err(MuSigError::NotEnoughShares);
+    }
+    if shares.iter().any(|(index, _)| *index >= params.n) {
+        return Err(MuSigError::IndexOutOfRange);
+    }
+    let indices: Vec<usize> = shares.iter().map(|(index, _)| *index).collect();
+    for (k, index) in indices.iter().enumerate() {
+        if indices[..k].contains(index) {
+            return Err(MuSigError::DuplicateKey);
+        }
+    }
+    let zero = BigInt::from(0);
+    if shares
+        .iter()
+        .any(|(_, share)| *share < zero || *share >= curve_order())
+    {
+        return Err(MuSigError::InvalidScalar);
+    }
+    let secret = shares.iter().fold(FE::zero(), |acc, (index, share)| {
+        let share: FE = ECScalar::from(share);
+        acc + share * lagrange_coefficient(*index, &indices)
+    });
+    Ok(secret.to_big_int())
+}